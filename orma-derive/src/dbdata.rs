@@ -1,47 +1,28 @@
-use core::ops::Deref;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
-use syn::parse::ParseStream;
-
-use syn::parse::Parse;
 use syn::*;
 
 #[derive(Debug)]
 enum AttributeType {
     Table,
+    IdType,
+    IdColumns,
+    VersionColumn,
+    DataColumn,
+    IdStrategy,
+    SearchConfig,
     Unknown,
 }
 
-struct NamedField(Field);
-
-impl Deref for NamedField {
-    type Target = Field;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl From<Field> for NamedField {
-    fn from(field: Field) -> Self {
-        Self(field)
-    }
-}
-
-impl Into<Field> for NamedField {
-    fn into(self) -> Field {
-        self.0
-    }
-}
-
-impl Parse for NamedField {
-    fn parse(input: ParseStream) -> Result<Self> {
-        Field::parse_named(input).map(NamedField::from)
-    }
-}
-
 #[derive(Debug)]
 struct DbDataAttributes {
     table: Option<String>,
+    id_type: Option<String>,
+    id_columns: Option<String>,
+    version_column: Option<String>,
+    data_column: Option<String>,
+    id_strategy: Option<String>,
+    search_config: Option<String>,
 }
 
 impl From<&Ident> for AttributeType {
@@ -49,6 +30,12 @@ impl From<&Ident> for AttributeType {
         let str = ident.to_string();
         match str.as_str() {
             "table" => AttributeType::Table,
+            "id_type" => AttributeType::IdType,
+            "id_columns" => AttributeType::IdColumns,
+            "version_column" => AttributeType::VersionColumn,
+            "data_column" => AttributeType::DataColumn,
+            "id_strategy" => AttributeType::IdStrategy,
+            "search_config" => AttributeType::SearchConfig,
             _ => AttributeType::Unknown,
         }
     }
@@ -56,7 +43,15 @@ impl From<&Ident> for AttributeType {
 
 impl DbDataAttributes {
     fn default() -> Self {
-        Self { table: None }
+        Self {
+            table: None,
+            id_type: None,
+            id_columns: None,
+            version_column: None,
+            data_column: None,
+            id_strategy: None,
+            search_config: None,
+        }
     }
 }
 
@@ -68,6 +63,106 @@ fn lit_string(lit: &Lit) -> String {
     }
 }
 
+#[derive(Debug)]
+struct PromotedField {
+    field: String,
+    column: String,
+    unique: bool,
+}
+
+#[derive(Debug)]
+struct SearchField {
+    path: String,
+    weight: char,
+}
+
+#[derive(Debug)]
+struct ExtraColumn {
+    field: Ident,
+    column: String,
+}
+
+#[derive(Debug)]
+struct FieldAttributes {
+    promoted: Option<PromotedField>,
+    extra_column: Option<ExtraColumn>,
+    search: Option<SearchField>,
+}
+
+/// Parses (and removes) `#[orma(promoted)]`, `#[orma(promoted = "column")]`,
+/// `#[orma(unique)]`, `#[orma(column)]`, `#[orma(column = "column")]`, `#[orma(search)]`
+/// and `#[orma(search = "weight")]` field attributes.
+fn parse_field_attrs(field: &mut Field) -> FieldAttributes {
+    let ident = field.ident.as_ref().unwrap().to_string();
+    let (orma_attrs, attrs): (Vec<Attribute>, Vec<Attribute>) = field
+        .attrs
+        .drain(..)
+        .partition(|attr| attr.path.is_ident("orma"));
+    field.attrs = attrs;
+
+    let mut promoted: Option<String> = None;
+    let mut unique = false;
+    let mut extra_column: Option<String> = None;
+    let mut search: Option<char> = None;
+    for attr in orma_attrs {
+        let meta_list = if let Ok(Meta::List(meta_list)) = attr.parse_meta() {
+            meta_list
+        } else {
+            panic!("Invalid orma attribute on field {}", ident);
+        };
+        for nested in meta_list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("promoted") => {
+                    promoted = Some(ident.clone())
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("unique") => unique = true,
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.path.is_ident("promoted") =>
+                {
+                    promoted = Some(lit_string(&name_value.lit))
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("column") => {
+                    extra_column = Some(ident.clone())
+                }
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.path.is_ident("column") =>
+                {
+                    extra_column = Some(lit_string(&name_value.lit))
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("search") => search = Some('D'),
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.path.is_ident("search") =>
+                {
+                    let weight = lit_string(&name_value.lit);
+                    search = match weight.as_str() {
+                        "A" | "B" | "C" | "D" => weight.chars().next(),
+                        _ => panic!("Invalid search weight {} on field {}", weight, ident),
+                    }
+                }
+                _ => panic!("Unknown orma attribute on field {}", ident),
+            }
+        }
+    }
+    if extra_column.is_some() {
+        field.attrs.push(parse_quote! {#[serde(skip)]});
+    }
+    FieldAttributes {
+        search: search.map(|weight| SearchField {
+            path: ident.clone(),
+            weight,
+        }),
+        extra_column: extra_column.map(|column| ExtraColumn {
+            field: field.ident.clone().unwrap(),
+            column,
+        }),
+        promoted: promoted.map(|column| PromotedField {
+            field: ident,
+            column,
+            unique,
+        }),
+    }
+}
+
 fn parse_orma_attrs(attrs: &[NestedMeta]) -> DbDataAttributes {
     let mut ctx = DbDataAttributes::default();
     for attr in attrs {
@@ -78,8 +173,19 @@ fn parse_orma_attrs(attrs: &[NestedMeta]) -> DbDataAttributes {
         };
         if let Meta::NameValue(name_value) = meta {
             let attr_type = AttributeType::from(name_value.path.get_ident().unwrap());
-            if let AttributeType::Table = attr_type {
-                ctx.table = Some(lit_string(&name_value.lit))
+            match attr_type {
+                AttributeType::Table => ctx.table = Some(lit_string(&name_value.lit)),
+                AttributeType::IdType => ctx.id_type = Some(lit_string(&name_value.lit)),
+                AttributeType::IdColumns => ctx.id_columns = Some(lit_string(&name_value.lit)),
+                AttributeType::VersionColumn => {
+                    ctx.version_column = Some(lit_string(&name_value.lit))
+                }
+                AttributeType::DataColumn => ctx.data_column = Some(lit_string(&name_value.lit)),
+                AttributeType::IdStrategy => ctx.id_strategy = Some(lit_string(&name_value.lit)),
+                AttributeType::SearchConfig => {
+                    ctx.search_config = Some(lit_string(&name_value.lit))
+                }
+                AttributeType::Unknown => {}
             }
         };
     }
@@ -93,6 +199,60 @@ pub fn impl_orma(attrs: &[NestedMeta], input: &mut DeriveInput) -> TokenStream {
     } else {
         panic!("No table name provided, {:?}", attrs);
     };
+    let id_type: Type = match dbdata_attrs.id_type {
+        Some(id_type) => parse_str(&id_type).expect("Invalid id_type"),
+        None => parse_quote! {::uuid::Uuid},
+    };
+    let id_columns_count = dbdata_attrs
+        .id_columns
+        .as_ref()
+        .map_or(1, |id_columns| id_columns.split(',').count());
+    if let Type::Tuple(tuple) = &id_type {
+        if tuple.elems.len() != id_columns_count {
+            return Error::new(
+                input.ident.span(),
+                format!(
+                    "id_type has {} elements but {} id_columns are defined",
+                    tuple.elems.len(),
+                    id_columns_count
+                ),
+            )
+            .to_compile_error();
+        }
+    }
+    let id_columns = dbdata_attrs.id_columns.map(|id_columns| {
+        let id_columns = id_columns.split(',').map(str::trim);
+        quote! {
+            const ID_COLUMNS: &'static [&'static str] = &[#(#id_columns),*];
+        }
+    });
+    let version_column = dbdata_attrs.version_column.map(|version_column| {
+        quote! {
+            const VERSION_COLUMN: &'static str = #version_column;
+        }
+    });
+    let data_column = dbdata_attrs.data_column.map(|data_column| {
+        quote! {
+            const DATA_COLUMN: &'static str = #data_column;
+        }
+    });
+    let id_strategy = dbdata_attrs.id_strategy.map(|id_strategy| {
+        let id_strategy = match id_strategy.as_str() {
+            "random" => quote! {Random},
+            "time_ordered" => quote! {TimeOrdered},
+            "ulid" => quote! {Ulid},
+            "database" => quote! {Database},
+            _ => panic!("Unknown id_strategy {}", id_strategy),
+        };
+        quote! {
+            const ID_STRATEGY: ::orma::IdStrategy = ::orma::IdStrategy::#id_strategy;
+        }
+    });
+    let search_config = dbdata_attrs.search_config.map(|search_config| {
+        quote! {
+            const SEARCH_CONFIG: &'static str = #search_config;
+        }
+    });
     let data = if let Data::Struct(ref mut it) = input.data {
         it
     } else {
@@ -106,13 +266,75 @@ pub fn impl_orma(attrs: &[NestedMeta], input: &mut DeriveInput) -> TokenStream {
             .to_compile_error();
     };
 
+    let field_attrs: Vec<FieldAttributes> =
+        fields.named.iter_mut().map(parse_field_attrs).collect();
+    let mut promoted_fields: Vec<PromotedField> = vec![];
+    let mut extra_columns: Vec<ExtraColumn> = vec![];
+    let mut search_fields: Vec<SearchField> = vec![];
+    for attrs in field_attrs {
+        promoted_fields.extend(attrs.promoted);
+        extra_columns.extend(attrs.extra_column);
+        search_fields.extend(attrs.search);
+    }
+    let extra_columns = if extra_columns.is_empty() {
+        None
+    } else {
+        let columns = extra_columns.iter().map(|extra| &extra.column);
+        let reads = extra_columns.iter().enumerate().map(|(i, extra)| {
+            let field = &extra.field;
+            quote! {
+                self.#field = row.get(idx + #i);
+            }
+        });
+        Some(quote! {
+            const EXTRA_COLUMNS: &'static [&'static str] = &[#(#columns),*];
+
+            fn read_extra_columns(&mut self, row: &::orma::Row, idx: usize) {
+                #(#reads)*
+            }
+        })
+    };
+    let promoted_fields = promoted_fields.iter().map(
+        |PromotedField {
+             field,
+             column,
+             unique,
+         }| {
+            quote! {
+                ::orma::PromotedField {
+                    field: #field,
+                    column: #column,
+                    unique: #unique,
+                }
+            }
+        },
+    );
+
+    let search_fields = if search_fields.is_empty() {
+        None
+    } else {
+        let search_fields = search_fields.iter().map(|SearchField { path, weight }| {
+            quote! {
+                ::orma::SearchField {
+                    path: #path,
+                    weight: #weight,
+                }
+            }
+        });
+        Some(quote! {
+            fn search_fields() -> &'static [::orma::SearchField] {
+                &[#(#search_fields),*]
+            }
+        })
+    };
+
     let skip: Attribute = parse_quote! {#[serde(skip)]};
     fields.named.push(Field {
         attrs: vec![skip],
         vis: parse_quote! {pub},
         ident: Some(Ident::new("orma_id", Span::call_site())),
         colon_token: None,
-        ty: parse_quote! {Option<::uuid::Uuid>},
+        ty: parse_quote! {Option<#id_type>},
     });
 
     let skip: Attribute = parse_quote! {#[serde(skip)]};
@@ -128,18 +350,29 @@ pub fn impl_orma(attrs: &[NestedMeta], input: &mut DeriveInput) -> TokenStream {
     quote! {
         #input
         impl ::orma::DbData for #ident {
+            type Id = #id_type;
+            #id_columns
+            #version_column
+            #data_column
+            #id_strategy
+            #search_config
+
             fn table_name() -> &'static str {
                 #table_name
             }
-            fn id(&self) -> Option<::uuid::Uuid> {self.orma_id}
+            fn promoted_fields() -> &'static [::orma::PromotedField] {
+                &[#(#promoted_fields),*]
+            }
+            fn id(&self) -> Option<#id_type> {self.orma_id.clone()}
             fn version(&self) -> Option<i32> {self.orma_version}
-            fn set_id(&mut self, uuid: ::uuid::Uuid) {
-                self.orma_id = Some(uuid);
+            fn set_id(&mut self, id: #id_type) {
+                self.orma_id = Some(id);
             }
             fn set_version(&mut self, version: i32) {
                 self.orma_version = Some(version);
             }
-
+            #extra_columns
+            #search_fields
         }
     }
 }
@@ -43,6 +43,37 @@ use syn::{parse_macro_input, AttributeArgs, DeriveInput};
 ///
 /// ```
 ///
+/// The primary key is a `Uuid` mapped over the `id` column unless `id_type` (and `id_columns`
+/// for composite keys) are given, as in
+/// `#[orma_obj(table = "table_name", id_type = "(String, i64)", id_columns = "code, year")]`.
+/// Likewise `version_column` and `data_column` rename the `version` and `data` columns.
+///
+/// New keys are random UUIDs; `id_strategy` can be set to `"time_ordered"` (UUIDv7), `"ulid"`
+/// or `"database"` (keys generated by column defaults and read back on insert).
+///
+/// Fields can be promoted to generated columns with `#[orma(promoted)]`
+/// (or `#[orma(promoted = "column_name")]`), adding `unique` for a unique index.
+///
+/// ```edition2018
+///  use serde::{Serialize, Deserialize};
+///
+///  #[orma_derive::orma_obj(table = "table_name")]
+///  #[derive(Serialize, Deserialize)]
+///  struct TestData {
+///      #[orma(promoted, unique)]
+///      email: String,
+///      some_other_filed: String,
+///  }
+///
+/// ```
+///
+/// Fields marked with `#[orma(column)]` (or `#[orma(column = "column_name")]`) are not part of
+/// the document: they are read from an extra table column, also after inserts and updates.
+///
+/// Fields marked with `#[orma(search)]` (or `#[orma(search = "A")]`, weights going from "A" to "D")
+/// are indexed for full-text search, using the text search configuration given by the
+/// `search_config` attribute (such as `search_config = "english"`).
+///
 #[proc_macro_attribute]
 pub fn orma_obj(
     args: proc_macro::TokenStream,
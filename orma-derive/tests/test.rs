@@ -12,6 +12,89 @@ fn proc_macro_attr_table_attr() {
     assert_eq!(Foo::table_name(), "xxx");
 }
 
+#[test]
+fn proc_macro_attr_promoted_fields() {
+    #[orma_obj(table = "xxx")]
+    #[derive(Serialize, Deserialize)]
+    struct Foo {
+        #[orma(promoted, unique)]
+        name: String,
+        #[orma(promoted = "foo_descr")]
+        description: String,
+        other: String,
+    }
+    assert_eq!(
+        Foo::promoted_fields(),
+        &[
+            orma::PromotedField {
+                field: "name",
+                column: "name",
+                unique: true,
+            },
+            orma::PromotedField {
+                field: "description",
+                column: "foo_descr",
+                unique: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn proc_macro_attr_search_fields() {
+    #[orma_obj(table = "xxx", search_config = "english")]
+    #[derive(Serialize, Deserialize)]
+    struct Foo {
+        #[orma(search = "A")]
+        title: String,
+        #[orma(search)]
+        body: String,
+        other: String,
+    }
+    assert_eq!(Foo::SEARCH_CONFIG, "english");
+    assert_eq!(
+        Foo::search_fields(),
+        &[
+            orma::SearchField {
+                path: "title",
+                weight: 'A',
+            },
+            orma::SearchField {
+                path: "body",
+                weight: 'D',
+            },
+        ]
+    );
+}
+
+#[test]
+fn proc_macro_attr_column_names() {
+    #[orma_obj(
+        table = "xxx",
+        id_columns = "uid",
+        version_column = "rev",
+        data_column = "doc"
+    )]
+    #[derive(Serialize, Deserialize)]
+    struct Foo {
+        name: String,
+    }
+    assert_eq!(Foo::ID_COLUMNS, &["uid"]);
+    assert_eq!(Foo::VERSION_COLUMN, "rev");
+    assert_eq!(Foo::DATA_COLUMN, "doc");
+    assert_eq!(Foo::select_part(), "SELECT uid, rev, doc FROM xxx");
+}
+
+#[test]
+fn proc_macro_attr_id_strategy() {
+    #[orma_obj(table = "xxx", id_strategy = "time_ordered")]
+    #[derive(Serialize, Deserialize)]
+    struct Foo {
+        name: String,
+    }
+    assert_eq!(Foo::ID_STRATEGY, orma::IdStrategy::TimeOrdered);
+}
+
 fn data(input: &str) -> &str {
     input
 }
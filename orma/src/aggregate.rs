@@ -0,0 +1,256 @@
+use crate::{
+    dbentity, filter, json_path_expr, quote_literal, Cast, Connection, DbData, DbError, ToSql,
+};
+
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+enum AggregateFn {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+struct AggregateColumn {
+    name: String,
+    function: AggregateFn,
+    expr: String,
+}
+
+/// Builder for group-by/aggregate queries over the documents of a DbData.
+///
+/// Every group and aggregate has a name, and each resulting row is decoded into
+/// a struct whose fields are named after them.
+///
+/// Filter and having are tuples with the query clause and its values, each numbering its
+/// parameters from `$1`.
+/// ## Example
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct UsersByGroup {
+///     group: String,
+///     users: i64,
+///     avg_age: Option<f64>,
+/// }
+///
+/// let rows: Vec<UsersByGroup> = DbEntity::<User>::aggregate()
+///     .group_by("group", "group", Cast::Text)
+///     .count("users")
+///     .avg("avg_age", "age", Cast::Numeric)
+///     .filter(("data->>'active' = $1", &[&"true"]))
+///     .having(("count(*) > $1", &[&1i64]))
+///     .fetch(&conn)
+///     .await?;
+/// ```
+pub struct Aggregate<'a, T>
+where
+    T: DbData,
+{
+    groups: Vec<(String, String)>,
+    aggregates: Vec<AggregateColumn>,
+    filter: Option<(&'a str, &'a [&'a (dyn ToSql + Sync)])>,
+    having: Option<(&'a str, &'a [&'a (dyn ToSql + Sync)])>,
+    data: PhantomData<T>,
+}
+
+impl<'a, T> Default for Aggregate<'a, T>
+where
+    T: DbData,
+{
+    fn default() -> Self {
+        Self {
+            groups: vec![],
+            aggregates: vec![],
+            filter: None,
+            having: None,
+            data: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Aggregate<'a, T>
+where
+    T: DbData,
+{
+    /// Creates an empty aggregation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Groups rows by the value at the document *path*
+    pub fn group_by(mut self, name: &str, path: &str, cast: Cast) -> Self {
+        self.groups
+            .push((name.to_owned(), json_path_expr(T::DATA_COLUMN, path, cast)));
+        self
+    }
+
+    fn aggregate(mut self, name: &str, function: AggregateFn, expr: String) -> Self {
+        self.aggregates.push(AggregateColumn {
+            name: name.to_owned(),
+            function,
+            expr,
+        });
+        self
+    }
+
+    /// Counts the rows of each group
+    pub fn count(self, name: &str) -> Self {
+        self.aggregate(name, AggregateFn::Count, "*".to_owned())
+    }
+
+    /// Sums the values at the document *path*
+    pub fn sum(self, name: &str, path: &str, cast: Cast) -> Self {
+        let expr = json_path_expr(T::DATA_COLUMN, path, cast);
+        self.aggregate(name, AggregateFn::Sum, expr)
+    }
+
+    /// Averages the values at the document *path*
+    pub fn avg(self, name: &str, path: &str, cast: Cast) -> Self {
+        let expr = json_path_expr(T::DATA_COLUMN, path, cast);
+        self.aggregate(name, AggregateFn::Avg, expr)
+    }
+
+    /// Minimum of the values at the document *path*
+    pub fn min(self, name: &str, path: &str, cast: Cast) -> Self {
+        let expr = json_path_expr(T::DATA_COLUMN, path, cast);
+        self.aggregate(name, AggregateFn::Min, expr)
+    }
+
+    /// Maximum of the values at the document *path*
+    pub fn max(self, name: &str, path: &str, cast: Cast) -> Self {
+        let expr = json_path_expr(T::DATA_COLUMN, path, cast);
+        self.aggregate(name, AggregateFn::Max, expr)
+    }
+
+    /// Filters the rows before they are grouped
+    pub fn filter(mut self, filter: (&'a str, &'a [&'a (dyn ToSql + Sync)])) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Filters the groups. Parameters are numbered from `$1`, as in the filter.
+    pub fn having(mut self, having: (&'a str, &'a [&'a (dyn ToSql + Sync)])) -> Self {
+        self.having = Some(having);
+        self
+    }
+
+    pub(crate) fn sql(&self) -> String {
+        let columns = self
+            .groups
+            .iter()
+            .map(|(name, expr)| format!("{}, {}", quote_literal(name), expr))
+            .chain(self.aggregates.iter().map(|aggregate| {
+                format!(
+                    "{}, {}({})",
+                    quote_literal(&aggregate.name),
+                    match aggregate.function {
+                        AggregateFn::Count => "count",
+                        AggregateFn::Sum => "sum",
+                        AggregateFn::Avg => "avg",
+                        AggregateFn::Min => "min",
+                        AggregateFn::Max => "max",
+                    },
+                    aggregate.expr
+                )
+            }))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            "SELECT jsonb_build_object({columns}) FROM {table_name}{where}{group_by}{having}",
+            columns = columns,
+            table_name = T::table_name(),
+            where = match self.filter {
+                Some((filter, _)) => format!(
+                    " WHERE {}",
+                    dbentity::promote_fields(filter, T::DATA_COLUMN, T::promoted_fields())
+                ),
+                None => String::from(""),
+            },
+            group_by = if self.groups.is_empty() {
+                String::from("")
+            } else {
+                format!(
+                    " GROUP BY {}",
+                    self.groups
+                        .iter()
+                        .map(|(_, expr)| expr.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                )
+            },
+            having = match self.having {
+                Some((having, _)) => format!(
+                    " HAVING {}",
+                    filter::shift_params(having, self.filter.map_or(0, |(_, params)| params.len()))
+                ),
+                None => String::from(""),
+            },
+        )
+    }
+
+    /// Runs the query and decodes each row into *R*
+    pub async fn fetch<R>(&self, conn: &Connection) -> Result<Vec<R>, DbError>
+    where
+        R: DeserializeOwned,
+    {
+        dbentity::check_names::<T>()?;
+        let prepared_s = conn.prepare(&self.sql()).await?;
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        if let Some((_, filter_val)) = self.filter {
+            params.extend(filter_val);
+        }
+        if let Some((_, having_val)) = self.having {
+            params.extend(having_val);
+        }
+        conn.query(&prepared_s, &params)
+            .await?
+            .iter()
+            .map(|row| serde_json::from_value(row.get(0)).map_err(DbError::from))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::*;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize)]
+    struct Order {}
+
+    impl DbData for Order {
+        type Id = Uuid;
+
+        fn table_name() -> &'static str {
+            "intrared.orders"
+        }
+        fn id(&self) -> Option<Uuid> {
+            None
+        }
+        fn version(&self) -> Option<i32> {
+            None
+        }
+        fn set_id(&mut self, _uuid: Uuid) {}
+        fn set_version(&mut self, _version: i32) {}
+    }
+
+    #[test]
+    fn test_aggregate_sql() {
+        let aggregate = Aggregate::<Order>::new()
+            .group_by("status", "status", Cast::Text)
+            .count("orders")
+            .sum("total", "amount.value", Cast::Numeric)
+            .filter(("data->>'region' = $1", &[&"EU"]))
+            .having(("count(*) > $1", &[&1i64]));
+
+        assert_eq!(
+            aggregate.sql(),
+            "SELECT jsonb_build_object('status', data->>'status', 'orders', count(*), \
+             'total', sum((data->'amount'->>'value')::numeric)) FROM intrared.orders \
+             WHERE data->>'region' = $1 GROUP BY data->>'status' HAVING count(*) > $2"
+        );
+    }
+}
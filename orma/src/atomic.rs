@@ -0,0 +1,346 @@
+use crate::{
+    dbentity, dbkey, filter, Connection, DbData, DbEntity, DbError, DbKey, Json, Row, ToSql,
+};
+
+use serde::Serialize;
+
+/// Segments of a dot separated document path, as bound to `text[]` parameters
+pub(crate) fn path_segments(path: &str) -> Vec<String> {
+    path.split('.').map(str::to_owned).collect()
+}
+
+/// *data_column* with the missing parents of the path bound to `$<path_param>` (of *depth*
+/// segments) set to empty objects, so that a value can be set at the path with jsonb_set
+fn with_parents(data_column: &str, path_param: usize, depth: usize) -> String {
+    (1..depth).fold(data_column.to_owned(), |doc, level| {
+        format!(
+            "jsonb_set({doc}, (${path}::text[])[1:{level}], \
+             coalesce({data_column} #> (${path}::text[])[1:{level}], '{{}}'::jsonb))",
+            doc = doc,
+            path = path_param,
+            level = level,
+            data_column = data_column,
+        )
+    })
+}
+
+impl<T> DbEntity<T>
+where
+    T: DbData,
+{
+    /// Sets the data column to *data_expr* on server side, increments the version and reads
+    /// back the record. *data_expr* and the optional *condition* use the parameters *params*,
+    /// numbered after the key parameters (starting from `$<Id::COLUMNS + 1>`).
+    ///
+    /// The optional *returning* expression is read back after the entity columns.
+    ///
+    /// Returns None when the record was not updated (missing record or false condition).
+    pub(crate) async fn update_data_with(
+        &mut self,
+        conn: &Connection,
+        data_expr: &str,
+        condition: Option<&str>,
+        returning: Option<&str>,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, DbError> {
+        dbentity::check_names::<T>()?;
+        let prepared_s = conn
+            .prepare(&format!(
+                "UPDATE {table_name} SET {data_column} = {data_expr}, {version_column} = {version_column} + 1 \
+                 WHERE {id_condition}{condition} RETURNING {columns}{returning}",
+                table_name = T::table_name(),
+                data_column = T::DATA_COLUMN,
+                data_expr = data_expr,
+                version_column = T::VERSION_COLUMN,
+                id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+                condition = match condition {
+                    Some(condition) => format!(" AND ({})", condition),
+                    None => String::from(""),
+                },
+                columns = dbentity::entity_columns::<T>(None),
+                returning = match returning {
+                    Some(returning) => format!(", {}", returning),
+                    None => String::from(""),
+                },
+            ))
+            .await?;
+        let mut all_params = self.id.params();
+        all_params.extend(params);
+        let row = conn.query_opt(&prepared_s, &all_params).await?;
+        if let Some(row) = &row {
+            *self = Self::from_row(row)?;
+        }
+        Ok(row)
+    }
+
+    /// Sets the array at *path* to *array_expr*. When *create* is false the stored document
+    /// must already hold an array at *path*, otherwise it must hold an array or nothing.
+    async fn update_array(
+        &mut self,
+        conn: &Connection,
+        array_expr: &str,
+        path: &str,
+        value: &(dyn ToSql + Sync),
+        create: bool,
+    ) -> Result<(), DbError> {
+        let segments = path_segments(path);
+        let data_expr = format!(
+            "jsonb_set({document}, ${path}::text[], {array_expr})",
+            document = with_parents(T::DATA_COLUMN, T::Id::COLUMNS + 1, segments.len()),
+            path = T::Id::COLUMNS + 1,
+            array_expr = array_expr,
+        );
+        let is_array = format!(
+            "jsonb_typeof({data_column} #> ${path}::text[]) = 'array'",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+        );
+        // a parent which is not an object leaves the document unchanged: nothing is written then
+        let condition = if create {
+            format!(
+                "({is_array} OR {data_column} #> ${path}::text[] IS NULL) \
+                 AND ({data_expr}) #> ${path}::text[] IS NOT NULL",
+                is_array = is_array,
+                data_column = T::DATA_COLUMN,
+                data_expr = data_expr,
+                path = T::Id::COLUMNS + 1,
+            )
+        } else {
+            is_array
+        };
+        if self
+            .update_data_with(
+                conn,
+                &data_expr,
+                Some(&condition),
+                None,
+                &[&segments, value],
+            )
+            .await?
+            .is_some()
+        {
+            Ok(())
+        } else {
+            Err(DbError::new(
+                &format!("{:?} not found or {} is not an array path", self.id, path),
+                None,
+            ))
+        }
+    }
+
+    /// Atomically appends *value* to the array at *path* (dot separated, such as "tags") of
+    /// the stored document, creating the array and its missing parent objects. When *path*
+    /// holds a value which is not an array nothing is written and an error is returned.
+    ///
+    /// The version is incremented and the entity is updated with the stored record,
+    /// whatever its previous version.
+    /// ## Example
+    /// ```ignore
+    /// product.array_append(db_conn, "tags", &"sale").await?;
+    /// ```
+    pub async fn array_append<V>(
+        &mut self,
+        conn: &Connection,
+        path: &str,
+        value: &V,
+    ) -> Result<(), DbError>
+    where
+        V: Serialize,
+    {
+        let array_expr = format!(
+            "coalesce({data_column} #> ${path}::text[], '[]'::jsonb) || jsonb_build_array(${value}::jsonb)",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+            value = T::Id::COLUMNS + 2,
+        );
+        self.update_array(
+            conn,
+            &array_expr,
+            path,
+            &Json(serde_json::to_value(value)?),
+            true,
+        )
+        .await
+    }
+
+    /// Atomically inserts *value* at the beginning of the array at *path* of the stored document.
+    /// See [array_append](#method.array_append).
+    pub async fn array_prepend<V>(
+        &mut self,
+        conn: &Connection,
+        path: &str,
+        value: &V,
+    ) -> Result<(), DbError>
+    where
+        V: Serialize,
+    {
+        let array_expr = format!(
+            "jsonb_build_array(${value}::jsonb) || coalesce({data_column} #> ${path}::text[], '[]'::jsonb)",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+            value = T::Id::COLUMNS + 2,
+        );
+        self.update_array(
+            conn,
+            &array_expr,
+            path,
+            &Json(serde_json::to_value(value)?),
+            true,
+        )
+        .await
+    }
+
+    /// Atomically removes every item equal to *value* from the array at *path* of the stored
+    /// document. See [array_append](#method.array_append), but a missing array is an error.
+    pub async fn array_remove<V>(
+        &mut self,
+        conn: &Connection,
+        path: &str,
+        value: &V,
+    ) -> Result<(), DbError>
+    where
+        V: Serialize,
+    {
+        let array_expr = format!(
+            "(SELECT coalesce(jsonb_agg(item ORDER BY idx), '[]'::jsonb) \
+             FROM jsonb_array_elements({data_column} #> ${path}::text[]) WITH ORDINALITY AS items(item, idx) \
+             WHERE item <> ${value}::jsonb)",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+            value = T::Id::COLUMNS + 2,
+        );
+        self.update_array(
+            conn,
+            &array_expr,
+            path,
+            &Json(serde_json::to_value(value)?),
+            false,
+        )
+        .await
+    }
+
+    /// Atomically removes the item at *index* (negative indexes count from the end) from the
+    /// array at *path* of the stored document. See [array_remove](#method.array_remove).
+    pub async fn array_remove_at(
+        &mut self,
+        conn: &Connection,
+        path: &str,
+        index: i32,
+    ) -> Result<(), DbError> {
+        let array_expr = format!(
+            "({data_column} #> ${path}::text[]) - ${index}::integer",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+            index = T::Id::COLUMNS + 2,
+        );
+        self.update_array(conn, &array_expr, path, &index, false)
+            .await
+    }
+
+    /// Atomically adds *delta* to the number at *path* (dot separated, such as "stock.available")
+    /// of the stored document, without reading it first. A missing value counts as 0 and its
+    /// missing parent objects are created.
+    ///
+    /// The optional *guard* is a filter tuple, as in find_all, checked against the stored
+    /// record before the increment: when it is not satisfied (or the record is missing)
+    /// nothing is written and None is returned.
+    /// Otherwise the entity is updated with the stored record and the new value is returned
+    /// along with the new version (the value is read from the database, so *path* does not
+    /// need to be a field of *T*). When the value could not be set, as when a parent of *path*
+    /// is not an object, nothing is written and an error is returned.
+    /// ## Example
+    /// ```ignore
+    /// // take 3 items, unless fewer are available
+    /// let result = product
+    ///     .increment(db_conn, "stock", -3.0, Some(("(data->>'stock')::numeric >= $1", &[&3])))
+    ///     .await?;
+    /// if let Some((stock, version)) = result { ... }
+    /// ```
+    pub async fn increment(
+        &mut self,
+        conn: &Connection,
+        path: &str,
+        delta: f64,
+        guard: Option<(&str, &[&(dyn ToSql + Sync)])>,
+    ) -> Result<Option<(f64, i32)>, DbError> {
+        let segments = path_segments(path);
+        let data_expr = format!(
+            "jsonb_set({document}, ${path}::text[], \
+             to_jsonb(coalesce(({data_column} #>> ${path}::text[])::numeric, 0) + ${delta}::float8::numeric))",
+            document = with_parents(T::DATA_COLUMN, T::Id::COLUMNS + 1, segments.len()),
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+            delta = T::Id::COLUMNS + 2,
+        );
+        // a parent which is not an object leaves the document unchanged: nothing is written then
+        let number_check = format!(
+            "jsonb_typeof(({data_expr}) #> ${path}::text[]) = 'number'",
+            data_expr = data_expr,
+            path = T::Id::COLUMNS + 1,
+        );
+        let condition = match guard {
+            Some((condition, _)) => format!(
+                "{} AND ({})",
+                number_check,
+                filter::shift_params(
+                    &dbentity::promote_fields(condition, T::DATA_COLUMN, T::promoted_fields()),
+                    T::Id::COLUMNS + 2,
+                )
+            ),
+            None => number_check.clone(),
+        };
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&segments, &delta];
+        if let Some((_, guard_params)) = guard {
+            params.extend(guard_params);
+        }
+        let returning = format!(
+            "({data_column} #>> ${path}::text[])::float8",
+            data_column = T::DATA_COLUMN,
+            path = T::Id::COLUMNS + 1,
+        );
+        let row = self
+            .update_data_with(
+                conn,
+                &data_expr,
+                Some(&condition),
+                Some(&returning),
+                &params,
+            )
+            .await?;
+        match row {
+            Some(row) => {
+                let value_idx = T::Id::COLUMNS + 2 + T::EXTRA_COLUMNS.len();
+                match row.get::<_, Option<f64>>(value_idx) {
+                    Some(value) => Ok(Some((value, self.version))),
+                    None => Err(self.not_a_number_err(path)),
+                }
+            }
+            None => {
+                // tells a path that cannot hold a number from a false guard or a missing record
+                let prepared_s = conn
+                    .prepare(&format!(
+                        "SELECT {number_check} FROM {table_name} WHERE {id_condition}",
+                        number_check = number_check,
+                        table_name = T::table_name(),
+                        id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+                    ))
+                    .await?;
+                let mut check_params = self.id.params();
+                check_params.extend(&[&segments as &(dyn ToSql + Sync), &delta]);
+                match conn.query_opt(&prepared_s, &check_params).await? {
+                    Some(row) if row.get::<_, Option<bool>>(0) != Some(true) => {
+                        Err(self.not_a_number_err(path))
+                    }
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    fn not_a_number_err(&self, path: &str) -> DbError {
+        DbError::new(
+            &format!("{:?}: cannot set a number at {}", self.id, path),
+            None,
+        )
+    }
+}
@@ -1,4 +1,9 @@
-use super::{DbError, Row, SimpleQueryMessage, Statement, ToSql, ToStatement};
+use super::{
+    DbError, Row, SimpleQueryMessage, Statement, ToSql, ToStatement, Transaction,
+    TransactionBuilder,
+};
+use futures::future::BoxFuture;
+use std::sync::Mutex;
 use tokio_postgres::Client;
 
 /// Wrapper over tokio_postgres::Client
@@ -26,6 +31,8 @@ use tokio_postgres::Client;
 pub struct Connection {
     client: Client,
     transaction_n: u32,
+    /// Rollbacks of dropped transactions, sent before the next statement
+    pending_rollbacks: Mutex<Vec<String>>,
 }
 
 impl From<Client> for Connection {
@@ -33,18 +40,47 @@ impl From<Client> for Connection {
         Self {
             client,
             transaction_n: 0,
+            pending_rollbacks: Mutex::new(vec![]),
         }
     }
 }
 
 impl Connection {
+    /// The client, once the pending rollbacks have been sent
+    async fn client(&self) -> Result<&Client, DbError> {
+        let pending = std::mem::take(&mut *self.pending_rollbacks.lock().unwrap());
+        if !pending.is_empty() {
+            self.client.batch_execute(&pending.join("; ")).await?;
+        }
+        Ok(&self.client)
+    }
+
+    fn rollback_statement(&self) -> String {
+        if self.transaction_n == 1 {
+            String::from("ROLLBACK")
+        } else {
+            format!("ROLLBACK TO SAVEPOINT pt{}", self.transaction_n - 1)
+        }
+    }
+
+    /// Closes the current transaction level without waiting: the rollback is sent before the
+    /// next statement.
+    pub(crate) fn rollback_later(&mut self) {
+        if self.transaction_n > 0 {
+            let qry = self.rollback_statement();
+            self.pending_rollbacks.lock().unwrap().push(qry);
+            self.transaction_n -= 1;
+        }
+    }
+
     /// Executes a sequence of SQL statements using the simple query protocol.
     ///
     /// Statements should be separated by semicolons. If an error occurs, execution of the
     /// sequence will stop at that point. This is intended for use when, for example, initializing
     /// a database schema.
     pub async fn batch_execute(&self, query: &str) -> Result<(), DbError> {
-        self.client
+        self.client()
+            .await?
             .batch_execute(query)
             .await
             .map_err(DbError::from)
@@ -69,7 +105,8 @@ impl Connection {
         T: ?Sized + ToStatement,
     {
         let statement = &statement.__convert().into_statement(self).await?;
-        self.client
+        self.client()
+            .await?
             .execute(&**statement, params)
             .await
             .map_err(DbError::from)
@@ -82,7 +119,8 @@ impl Connection {
     ///
     /// Prepared statements can only be used with the connection that created them.
     pub async fn prepare(&self, query: &str) -> Result<Statement, DbError> {
-        self.client
+        self.client()
+            .await?
             .prepare(query)
             .await
             .map(Statement::from)
@@ -90,7 +128,8 @@ impl Connection {
     }
 
     pub async fn simple_query(&self, query: &str) -> Result<Vec<SimpleQueryMessage>, DbError> {
-        self.client
+        self.client()
+            .await?
             .simple_query(query)
             .await
             .map(|rows| rows.into_iter().map(SimpleQueryMessage::from).collect())
@@ -114,7 +153,8 @@ impl Connection {
         T: ?Sized + ToStatement,
     {
         let statement = &statement.__convert().into_statement(self).await?;
-        self.client
+        self.client()
+            .await?
             .query(&**statement, params)
             .await
             .map(|rows| rows.into_iter().map(Row::from).collect())
@@ -138,7 +178,8 @@ impl Connection {
         T: ?Sized + ToStatement,
     {
         let statement = &statement.__convert().into_statement(self).await?;
-        self.client
+        self.client()
+            .await?
             .query_one(&**statement, params)
             .await
             .map(Row::from)
@@ -162,16 +203,37 @@ impl Connection {
         T: ?Sized + ToStatement,
     {
         let statement = &statement.__convert().into_statement(self).await?;
-        self.client
+        self.client()
+            .await?
             .query_opt(&**statement, params)
             .await
-            .map(|option_row| match option_row {
-                Some(row) => Some(Row::from(row)),
-                _ => None,
-            })
+            .map(|option_row| option_row.map(Row::from))
             .map_err(DbError::from)
     }
 
+    /// Begins a transaction (or creates a savepoint if a transaction already started), returning
+    /// a guard used in place of the connection.
+    ///
+    /// The guard must be [committed](struct.Transaction.html#method.commit): when it is
+    /// dropped (such as when an error is returned early, or when the future using it is
+    /// cancelled) the transaction is rolled back before the next statement of the connection.
+    /// ## Example
+    /// ```ignore
+    /// let mut tx = conn.begin().await?;
+    /// user.insert(&tx).await?;
+    /// groups.add_items(&mut tx, &[&user]).await?;
+    /// tx.commit().await?;
+    /// ```
+    pub async fn begin(&mut self) -> Result<Transaction<'_>, DbError> {
+        self.build_transaction().begin().await
+    }
+
+    /// Returns a builder of a transaction with isolation level, access mode and deferrable
+    /// options. See [TransactionBuilder](struct.TransactionBuilder.html).
+    pub fn build_transaction(&mut self) -> TransactionBuilder<'_> {
+        TransactionBuilder::new(self)
+    }
+
     /// Begins a transaction or creates a savepoint if a transaction already started
     /// ## Example
     /// from [dbjoin.rs](../src/orma/dbjoin.rs.html)
@@ -199,9 +261,18 @@ impl Connection {
     /// }
     /// ```
     pub async fn transaction(&mut self) -> Result<(), DbError> {
+        self.start_transaction("BEGIN").await
+    }
+
+    /// Number of transactions and savepoints started and not yet committed or rolled back
+    pub(crate) fn transaction_depth(&self) -> u32 {
+        self.transaction_n
+    }
+
+    /// Issues *begin*, or creates a savepoint if a transaction already started
+    pub(crate) async fn start_transaction(&mut self, begin: &str) -> Result<(), DbError> {
         let qry = if self.transaction_n == 0 {
-            // String::from("BEGIN")
-            "BEGIN".into()
+            begin.to_owned()
         } else {
             format!("SAVEPOINT pt{}", self.transaction_n)
         };
@@ -210,6 +281,28 @@ impl Connection {
         Ok(())
     }
 
+    /// Runs *f* in a transaction (or in a savepoint when a transaction already started): the
+    /// transaction is committed when *f* returns Ok, and rolled back when it returns an error
+    /// or panics.
+    /// ## Example
+    /// ```ignore
+    /// let user_id = conn
+    ///     .in_transaction(|tx| {
+    ///         Box::pin(async move {
+    ///             user.insert(tx).await?;
+    ///             groups.add_items(tx, &[&user]).await?;
+    ///             Ok(user.id)
+    ///         })
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn in_transaction<F, R>(&mut self, f: F) -> Result<R, DbError>
+    where
+        F: for<'c> FnOnce(&'c mut Connection) -> BoxFuture<'c, Result<R, DbError>>,
+    {
+        self.build_transaction().in_transaction(f).await
+    }
+
     /// Commits a transaction or releases a savepoint
     pub async fn commit(&mut self) -> Result<(), DbError> {
         if self.transaction_n == 0 {
@@ -231,11 +324,7 @@ impl Connection {
         if self.transaction_n == 0 {
             Err(DbError::new("Not in a transaction", None))
         } else {
-            let qry = if self.transaction_n == 1 {
-                String::from("ROLLBACK")
-            } else {
-                format!("ROLLBACK TO SAVEPOINT pt{}", self.transaction_n - 1)
-            };
+            let qry = self.rollback_statement();
             self.batch_execute(&qry).await?;
             self.transaction_n -= 1;
             Ok(())
@@ -0,0 +1,87 @@
+use bytes::buf::BufMutExt;
+use bytes::{BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::ops::Deref;
+use tokio_postgres::types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// Wrapper binding any serializable value as a json/jsonb query parameter,
+/// and reading json/jsonb columns into any deserializable type.
+///
+/// ## Example
+/// ```ignore
+/// let tags = vec!["rust", "orm"];
+/// let rows = conn
+///     .query("SELECT data FROM intrared.products WHERE data->'tags' @> $1", &[&Json(&tags)])
+///     .await?;
+/// let data: Json<Product> = rows[0].get(0);
+/// ```
+pub struct Json<T>(pub T);
+
+impl<T> Json<T> {
+    /// Unwraps the value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> fmt::Debug for Json<T>
+where
+    T: Serialize,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_string(&self.0) {
+            Ok(json) => write!(f, "Json({})", json),
+            Err(_) => f.write_str("Json(<unserializable>)"),
+        }
+    }
+}
+
+impl<T> ToSql for Json<T>
+where
+    T: Serialize,
+{
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        if *ty == Type::JSONB {
+            // jsonb binary format version
+            out.put_u8(1);
+        }
+        serde_json::to_writer(out.writer(), &self.0)?;
+        Ok(IsNull::No)
+    }
+
+    accepts!(JSON, JSONB);
+
+    to_sql_checked!();
+}
+
+impl<'a, T> FromSql<'a> for Json<T>
+where
+    T: Deserialize<'a>,
+{
+    fn from_sql(ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if *ty == Type::JSONB {
+            let mut version = [0; 1];
+            raw.read_exact(&mut version)?;
+            if version[0] != 1 {
+                return Err("unsupported JSONB encoding version".into());
+            }
+        }
+        Ok(Json(serde_json::from_slice(raw)?))
+    }
+
+    accepts!(JSON, JSONB);
+}
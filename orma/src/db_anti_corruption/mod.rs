@@ -1,13 +1,17 @@
 // use std::ops::{Deref};
 mod connection;
 mod db_error;
+mod json;
 mod row;
 mod statement;
 pub mod tls;
+mod transaction;
 
 pub use connection::Connection;
 pub use db_error::DbError;
+pub use json::Json;
 pub use row::{Row, SimpleQueryMessage, SimpleQueryRow};
 pub use statement::{Statement, ToStatement};
 pub use tokio_postgres::types::ToSql;
 pub use tokio_postgres::{Client, Config, NoTls, RowStream, Socket};
+pub use transaction::{IsolationLevel, Transaction, TransactionBuilder};
@@ -0,0 +1,208 @@
+use super::{Connection, DbError};
+use futures::future::{BoxFuture, FutureExt};
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Transaction isolation levels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+fn begin_statement(
+    isolation_level: Option<IsolationLevel>,
+    read_only: Option<bool>,
+    deferrable: Option<bool>,
+) -> String {
+    let mut modes = vec![];
+    if let Some(isolation_level) = isolation_level {
+        modes.push(format!("ISOLATION LEVEL {}", isolation_level.sql()));
+    }
+    match read_only {
+        Some(true) => modes.push(String::from("READ ONLY")),
+        Some(false) => modes.push(String::from("READ WRITE")),
+        None => (),
+    }
+    match deferrable {
+        Some(true) => modes.push(String::from("DEFERRABLE")),
+        Some(false) => modes.push(String::from("NOT DEFERRABLE")),
+        None => (),
+    }
+    if modes.is_empty() {
+        String::from("BEGIN")
+    } else {
+        format!("BEGIN {}", modes.join(", "))
+    }
+}
+
+/// Builder of a transaction with options, created by
+/// [Connection::build_transaction](struct.Connection.html#method.build_transaction).
+///
+/// Options not set follow the server defaults. They apply to the top-level transaction only:
+/// when a transaction already started a savepoint is created, which inherits the options of
+/// the transaction, so setting any option is an error then.
+/// ## Example
+/// ```ignore
+/// let tx = conn
+///     .build_transaction()
+///     .isolation_level(IsolationLevel::RepeatableRead)
+///     .read_only(true)
+///     .deferrable(true)
+///     .begin()
+///     .await?;
+/// ```
+pub struct TransactionBuilder<'a> {
+    conn: &'a mut Connection,
+    isolation_level: Option<IsolationLevel>,
+    read_only: Option<bool>,
+    deferrable: Option<bool>,
+}
+
+impl<'a> TransactionBuilder<'a> {
+    pub(crate) fn new(conn: &'a mut Connection) -> Self {
+        Self {
+            conn,
+            isolation_level: None,
+            read_only: None,
+            deferrable: None,
+        }
+    }
+
+    pub fn isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.isolation_level = Some(isolation_level);
+        self
+    }
+
+    /// READ ONLY when true, READ WRITE when false
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// DEFERRABLE when true, NOT DEFERRABLE when false. It only takes effect on
+    /// SERIALIZABLE READ ONLY transactions.
+    pub fn deferrable(mut self, deferrable: bool) -> Self {
+        self.deferrable = Some(deferrable);
+        self
+    }
+
+    /// Begins the transaction, as in [Connection::begin](struct.Connection.html#method.begin)
+    pub async fn begin(self) -> Result<Transaction<'a>, DbError> {
+        let has_options =
+            self.isolation_level.is_some() || self.read_only.is_some() || self.deferrable.is_some();
+        if has_options && self.conn.transaction_depth() > 0 {
+            return Err(DbError::new(
+                "Transaction options cannot be set when a transaction already started",
+                None,
+            ));
+        }
+        let begin = begin_statement(self.isolation_level, self.read_only, self.deferrable);
+        self.conn.start_transaction(&begin).await?;
+        Ok(Transaction::new(self.conn))
+    }
+
+    /// Runs *f* in the transaction, as in
+    /// [Connection::in_transaction](struct.Connection.html#method.in_transaction)
+    pub async fn in_transaction<F, R>(self, f: F) -> Result<R, DbError>
+    where
+        F: for<'c> FnOnce(&'c mut Connection) -> BoxFuture<'c, Result<R, DbError>>,
+    {
+        let mut tx = self.begin().await?;
+        match AssertUnwindSafe(f(&mut tx)).catch_unwind().await {
+            Ok(Ok(value)) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Ok(Err(e)) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+            Err(payload) => {
+                drop(tx);
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+/// A transaction (or savepoint) begun by [Connection::begin](struct.Connection.html#method.begin).
+///
+/// It is used in place of the connection. When it is dropped without being committed
+/// the transaction is rolled back, before the next statement of the connection.
+pub struct Transaction<'a> {
+    conn: &'a mut Connection,
+    done: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(conn: &'a mut Connection) -> Self {
+        Self { conn, done: false }
+    }
+
+    /// Commits the transaction or releases the savepoint
+    pub async fn commit(mut self) -> Result<(), DbError> {
+        self.conn.commit().await?;
+        self.done = true;
+        Ok(())
+    }
+
+    /// Rolls back the transaction or the savepoint
+    pub async fn rollback(mut self) -> Result<(), DbError> {
+        self.conn.rollback().await?;
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Connection;
+    fn deref(&self) -> &Self::Target {
+        self.conn
+    }
+}
+
+impl<'a> DerefMut for Transaction<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.conn.rollback_later();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_begin_statement() {
+        assert_eq!(begin_statement(None, None, None), "BEGIN");
+        assert_eq!(
+            begin_statement(Some(IsolationLevel::RepeatableRead), Some(true), Some(true)),
+            "BEGIN ISOLATION LEVEL REPEATABLE READ, READ ONLY, DEFERRABLE"
+        );
+        assert_eq!(
+            begin_statement(Some(IsolationLevel::Serializable), Some(false), Some(false)),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE, READ WRITE, NOT DEFERRABLE"
+        );
+    }
+}
@@ -1,49 +1,220 @@
-use crate::{db_anti_corruption::Connection, DbError, Row, ToSql};
+use crate::{
+    check_identifier, db_anti_corruption::Connection, dbkey, patch, Aggregate, DbError, DbKey,
+    Filter, IdStrategy, PatchOperation, Row, SearchField, Sort, ToSql,
+};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use serde_json;
 use std::ops::Deref;
-use uuid::Uuid;
 
-/// Helper function to create a SQL SELECT statement for a DbEntity table.
-/// This method returns the (id, version, data) tuple
-pub fn select_part(table_name: &str, distinct: bool, alias: Option<&str>) -> String {
-    let (alias, table_name) = if let Some(alias) = alias {
-        (format!("{}.", alias), format!("{} {}", table_name, alias))
-    } else {
-        ("".to_owned(), table_name.to_owned())
-    };
+fn columns_list(
+    (id_columns, version_column, data_column, extra_columns): (&[&str], &str, &str, &[&str]),
+    alias: Option<&str>,
+) -> String {
+    let mut columns = vec![dbkey::key_columns(id_columns, alias)];
+    columns.push(dbkey::key_columns(&[version_column, data_column], alias));
+    if !extra_columns.is_empty() {
+        columns.push(dbkey::key_columns(extra_columns, alias));
+    }
+    columns.join(", ")
+}
+
+fn select_columns(
+    table_name: &str,
+    columns: String,
+    distinct: bool,
+    alias: Option<&str>,
+) -> String {
     format!(
-        "{select} {alias}id, {alias}version, {alias}data FROM {table_name}",
+        "{select} {columns} FROM {table_name}",
         select = if distinct {
             "SELECT DISTINCT"
         } else {
             "SELECT"
         },
-        table_name = table_name,
-        alias = alias
+        columns = columns,
+        table_name = match alias {
+            Some(alias) => format!("{} {}", table_name, alias),
+            None => table_name.to_owned(),
+        },
+    )
+}
+
+/// The columns mapped by a DbEntity: id columns, version, data and extra columns.
+pub(crate) fn entity_columns<T: DbData>(alias: Option<&str>) -> String {
+    columns_list(
+        (
+            T::ID_COLUMNS,
+            T::VERSION_COLUMN,
+            T::DATA_COLUMN,
+            T::EXTRA_COLUMNS,
+        ),
+        alias,
+    )
+}
+
+/// Checks the table and column names of *T* before they are interpolated into queries.
+pub(crate) fn check_names<T: DbData>() -> Result<(), DbError> {
+    check_identifier(T::table_name())?;
+    for column in T::ID_COLUMNS
+        .iter()
+        .chain(&[T::VERSION_COLUMN, T::DATA_COLUMN])
+        .chain(T::EXTRA_COLUMNS)
+    {
+        check_identifier(column)?;
+    }
+    Ok(())
+}
+
+/// Helper function to create a SQL SELECT statement for a DbEntity table.
+/// This method returns the (id, version, data) tuple
+pub fn select_part(table_name: &str, distinct: bool, alias: Option<&str>) -> String {
+    select_columns(
+        table_name,
+        columns_list((&["id"], "version", "data", &[]), alias),
+        distinct,
+        alias,
     )
 }
 
+/// Like [select_part](fn.select_part.html), but selecting the id, version, data and extra columns
+/// configured for *T* (see [DbData::ID_COLUMNS](trait.DbData.html#associatedconstant.ID_COLUMNS)).
+pub fn select_part_for<T: DbData>(table_name: &str, distinct: bool, alias: Option<&str>) -> String {
+    select_columns(table_name, entity_columns::<T>(alias), distinct, alias)
+}
+
 /// Helper function to create the a SQL ORDER BY statement.
 ///
-/// *order_by* clauses are joined by ", " and *alias* is prepended with "."
-/// to each *order_by* element.
-pub fn make_sort_statement(order_by: &[&str], alias: Option<&str>) -> String {
-    match alias {
-        Some(alias) => order_by
-            .iter()
-            .map(|&item| format!("{}.{}", alias, item))
-            .collect::<Vec<String>>()
-            .join(", "),
-        _ => order_by.join(", "),
+/// *order_by* items are joined by ", ", where *data_column* is the document column
+/// and *alias* is prepended with "." to each of them.
+pub fn make_sort_statement(order_by: &[Sort], data_column: &str, alias: Option<&str>) -> String {
+    order_by
+        .iter()
+        .map(|item| item.sql(data_column, alias))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Rewrites every `data->>'field'` expression found in *expr* into the name of the
+/// generated column the field has been promoted to. *data_column* is the name of the
+/// document column (usually "data").
+///
+/// Alias prefixes (such as `a.data->>'email'`) are preserved, so the result is `a.email`.
+/// Quoted literals and identifiers and comments are left untouched.
+pub fn promote_fields(expr: &str, data_column: &str, promoted_fields: &[PromotedField]) -> String {
+    let patterns: Vec<(String, &str)> = promoted_fields
+        .iter()
+        .map(|promoted| {
+            (
+                format!("{}->>'{}'", data_column, promoted.field),
+                promoted.column,
+            )
+        })
+        .collect();
+    let mut result = String::with_capacity(expr.len());
+    let mut rest = expr;
+    while let Some(c) = rest.chars().next() {
+        let skip = match c {
+            '\'' | '"' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            _ => {
+                let preceded_by_ident = result
+                    .chars()
+                    .last()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                let promoted = patterns
+                    .iter()
+                    .find(|(pattern, _)| !preceded_by_ident && rest.starts_with(pattern.as_str()));
+                if let Some((pattern, column)) = promoted {
+                    result.push_str(column);
+                    rest = &rest[pattern.len()..];
+                    continue;
+                }
+                c.len_utf8()
+            }
+        };
+        result.push_str(&rest[..skip]);
+        rest = &rest[skip..];
     }
+    result
+}
+
+/// A top level document field that is also stored in a generated column.
+///
+/// Filters and sorting clauses referring to `data->>'field'` are rewritten to use the
+/// column, while [promoted_fields_ddl](fn.promoted_fields_ddl.html) produces the DDL to create it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PromotedField {
+    /// Name of the field inside the data document
+    pub field: &'static str,
+    /// Name of the generated column
+    pub column: &'static str,
+    /// When true the column index is created as UNIQUE
+    pub unique: bool,
+}
+
+/// Returns the DDL statements that add the generated columns (and their indexes) for the
+/// promoted fields of a DbData.
+///
+/// ## Example
+/// ```ignore
+/// for statement in promoted_fields_ddl::<User>() {
+///     conn.batch_execute(&statement).await?;
+/// }
+/// ```
+pub fn promoted_fields_ddl<T: DbData>() -> Vec<String> {
+    let table_name = T::table_name();
+    let short_name = table_name.rsplit('.').next().unwrap_or(table_name);
+    T::promoted_fields()
+        .iter()
+        .flat_map(|promoted| {
+            vec![
+                format!(
+                    "ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS {column} text GENERATED ALWAYS AS ({data_column}->>'{field}') STORED",
+                    table_name = table_name,
+                    data_column = T::DATA_COLUMN,
+                    column = promoted.column,
+                    field = promoted.field,
+                ),
+                format!(
+                    "CREATE {unique}INDEX IF NOT EXISTS {short_name}_{column}_ix ON {table_name} ({column})",
+                    unique = if promoted.unique { "UNIQUE " } else { "" },
+                    short_name = short_name,
+                    table_name = table_name,
+                    column = promoted.column,
+                ),
+            ]
+        })
+        .collect()
 }
 
 /// This trait is maps data in a data table and
 /// it's used along with DbEntity structure
 pub trait DbData: Serialize + DeserializeOwned {
+    /// The type of the primary key (Uuid, i64, String or a tuple for composite keys)
+    type Id: DbKey;
+
+    /// The primary key columns. Composite keys list one column for each key element.
+    const ID_COLUMNS: &'static [&'static str] = &["id"];
+
+    /// The column used for record versioning
+    const VERSION_COLUMN: &'static str = "version";
+
+    /// The jsonb column where the document is stored
+    const DATA_COLUMN: &'static str = "data";
+
+    /// How keys of new records are generated. Defaults to random UUIDs.
+    const ID_STRATEGY: IdStrategy = IdStrategy::Random;
+
+    /// Additional columns selected after (id, version, data) and read back after writes.
+    /// Their values are given to [read_extra_columns](#method.read_extra_columns).
+    const EXTRA_COLUMNS: &'static [&'static str] = &[];
+
+    /// The text search configuration used by full-text searches (such as "english").
+    /// Defaults to "simple".
+    const SEARCH_CONFIG: &'static str = "simple";
+
     /// The name of the db table where the implementing struct is mapped to.
     fn table_name() -> &'static str;
 
@@ -53,7 +224,7 @@ pub trait DbData: Serialize + DeserializeOwned {
     }
     /// Convenience function that returns the select part for the associated db table.
     fn select_part() -> String {
-        select_part(Self::table_name(), false, None)
+        select_part_for::<Self>(Self::table_name(), false, None)
     }
 
     /// Select part from instance
@@ -61,13 +232,42 @@ pub trait DbData: Serialize + DeserializeOwned {
         Self::select_part()
     }
 
+    /// Document fields promoted to generated columns. Defaults to none.
+    fn promoted_fields() -> &'static [PromotedField] {
+        &[]
+    }
+
+    /// Document fields indexed for full-text search. Defaults to none.
+    fn search_fields() -> &'static [SearchField] {
+        &[]
+    }
+
     /// returns the "id" column of the db row (same as DbEntity::id). If the DbData is not connected to the db, then result is None
-    fn id(&self) -> Option<Uuid>;
+    fn id(&self) -> Option<Self::Id>;
     /// returns the "version" column of the db row (same as DbEntity::version). If the DbData is not connected to the db, then result is None
     fn version(&self) -> Option<i32>;
 
-    fn set_id(&mut self, uuid: Uuid);
+    fn set_id(&mut self, id: Self::Id);
     fn set_version(&mut self, version: i32);
+
+    /// Reads the values of EXTRA_COLUMNS from a row, where the first extra column is at *idx*.
+    fn read_extra_columns(&mut self, _row: &Row, _idx: usize) {}
+
+    /// Called by [DbEntity::update](struct.DbEntity.html#method.update) before writing, with the
+    /// JSON Patch from the loaded document to the new one (such as for auditing).
+    /// An error aborts the update.
+    fn before_update(&self, _patch: &[PatchOperation]) -> Result<(), DbError> {
+        Ok(())
+    }
+}
+
+/// Key, version and loaded document of an entity, along with the document (and its key and
+/// version) of entities which were not loaded from the database
+pub(crate) struct SyncState<T: DbData> {
+    id: T::Id,
+    version: i32,
+    loaded: Option<serde_json::Value>,
+    data: Option<(serde_json::Value, Option<T::Id>, Option<i32>)>,
 }
 
 /// This struct is used to create a mapping for a data table.
@@ -76,12 +276,14 @@ where
     T: DbData + Serialize + DeserializeOwned,
 {
     /// This is the effective primary key of the record. Its also used to build relations with other tables
-    pub id: Uuid,
+    pub id: T::Id,
     /// This field is used as a record check and identifies possible conflicts for parallel operations.
     /// version should always autoinc on record update
     pub version: i32,
     /// The real information that a data table record is containing
     pub data: T,
+    /// The document as loaded from the database, used to compute changes
+    loaded: Option<serde_json::Value>,
 }
 
 impl<T> DbEntity<T>
@@ -89,126 +291,247 @@ where
     T: DbData + Serialize + DeserializeOwned,
 {
     /// Simple method used to create a new record
-    pub fn new(id: Uuid, version: i32, data: T) -> Self {
-        Self { id, version, data }
+    pub fn new(id: T::Id, version: i32, data: T) -> Self {
+        Self {
+            id,
+            version,
+            data,
+            loaded: None,
+        }
     }
 
     /// Given a data this method uses DbData#find_table_id_and_version to find a possible candidate for record or creates
     /// a new one that will need to be persisted with the insert method.
+    ///
+    /// New records get a key following [DbData::ID_STRATEGY](trait.DbData.html#associatedconstant.ID_STRATEGY).
+    /// Keys that cannot be generated start from their default value: with [IdStrategy::Database](enum.IdStrategy.html#variant.Database)
+    /// the key is filled in by insert, otherwise (as for natural keys) it must be assigned to *id*
+    /// before inserting the record.
     pub fn from_data(data: T) -> Self {
         match (data.id(), data.version()) {
-            (Some(uuid), Some(version)) => Self {
-                id: uuid,
-                version,
-                data,
-            },
-            _ => Self {
-                id: Uuid::new_v4(),
-                version: 0,
-                data,
-            },
+            (Some(id), Some(version)) => Self::new(id, version, data),
+            _ => Self::new(T::Id::generate(T::ID_STRATEGY).unwrap_or_default(), 0, data),
         }
     }
 
     /// Given a database row (id, version, data) returns a DbEntity.
     pub fn from_row(row: &Row) -> Result<Self, DbError> {
-        let uuid: Uuid = row.get(0);
-        let version: i32 = row.get(1);
-        let mut data: T = serde_json::from_value(row.get::<_, serde_json::Value>(2))
-            .map_err(DbError::from)
-            .unwrap();
-        data.set_id(uuid);
+        let id = T::Id::from_row(row, 0);
+        let version: i32 = row.get(T::Id::COLUMNS);
+        let mut data: T =
+            serde_json::from_value(row.get::<_, serde_json::Value>(T::Id::COLUMNS + 1))?;
+        data.set_id(id.clone());
         data.set_version(version);
-        Ok(DbEntity::new(uuid, version, data))
+        if !T::EXTRA_COLUMNS.is_empty() {
+            data.read_extra_columns(row, T::Id::COLUMNS + 2);
+        }
+        let loaded = serde_json::to_value(&data)?;
+        Ok(Self {
+            id,
+            version,
+            data,
+            loaded: Some(loaded),
+        })
+    }
+
+    /// True when the data changed since it was loaded from the database.
+    /// Entities that were not loaded from the database are always dirty.
+    pub fn is_dirty(&self) -> Result<bool, DbError> {
+        Ok(match &self.loaded {
+            Some(loaded) => *loaded != serde_json::to_value(&self.data)?,
+            None => true,
+        })
+    }
+
+    /// The JSON Pointers (such as "/address/city") of the values changed since the data was
+    /// loaded from the database. See [diff](#method.diff).
+    pub fn changed_paths(&self) -> Result<Vec<String>, DbError> {
+        Ok(self
+            .diff()?
+            .into_iter()
+            .map(|operation| match operation {
+                PatchOperation::Add { path, .. }
+                | PatchOperation::Remove { path }
+                | PatchOperation::Replace { path, .. }
+                | PatchOperation::Move { path, .. }
+                | PatchOperation::Copy { path, .. }
+                | PatchOperation::Test { path, .. } => path,
+            })
+            .collect())
+    }
+
+    /// The JSON Patch (RFC 6902) turning the document as loaded from the database into the
+    /// current data. Entities that were not loaded from the database get a single "replace"
+    /// of the whole document.
+    pub fn diff(&self) -> Result<Vec<PatchOperation>, DbError> {
+        let data = serde_json::to_value(&self.data)?;
+        Ok(match &self.loaded {
+            Some(loaded) => patch::diff(loaded, &data),
+            None => vec![PatchOperation::Replace {
+                path: String::from(""),
+                value: data,
+            }],
+        })
+    }
+
+    /// The state restored when the writes of the entity are rolled back
+    pub(crate) fn sync_state(&self) -> Result<SyncState<T>, DbError> {
+        Ok(SyncState {
+            id: self.id.clone(),
+            version: self.version,
+            data: match &self.loaded {
+                Some(_) => None,
+                None => Some((
+                    serde_json::to_value(&self.data)?,
+                    self.data.id(),
+                    self.data.version(),
+                )),
+            },
+            loaded: self.loaded.clone(),
+        })
+    }
+
+    pub(crate) fn restore_sync_state(&mut self, state: SyncState<T>) {
+        match state.data {
+            // new entities get their document back, without the key and version of the insert
+            Some((document, data_id, data_version)) => {
+                if let Ok(mut data) = serde_json::from_value::<T>(document) {
+                    if let Some(data_id) = data_id {
+                        data.set_id(data_id);
+                    }
+                    if let Some(data_version) = data_version {
+                        data.set_version(data_version);
+                    }
+                    self.data = data;
+                }
+            }
+            None => {
+                self.data.set_id(state.id.clone());
+                self.data.set_version(state.version);
+            }
+        }
+        self.id = state.id;
+        self.version = state.version;
+        self.loaded = state.loaded;
     }
 
     /// Given a database rows of (id, version, data) tuples returns a Vec of DbEntity.
     pub fn from_rows(rows: &[Row]) -> Result<Vec<Self>, DbError> {
-        if rows.is_empty() {
-            Ok(vec![])
-        } else {
-            Ok(rows
-                .iter()
-                .map(|row| DbEntity::from_row(&row).unwrap())
-                .collect())
-        }
+        rows.iter().map(DbEntity::from_row).collect()
     }
 
-    fn out_of_sync_err(&self) -> DbError {
-        DbError::new(&format!("{}:{} out of sync", self.id, self.version), None)
+    pub(crate) fn out_of_sync_err(&self) -> DbError {
+        DbError::new(&format!("{:?}:{} out of sync", self.id, self.version), None)
     }
 
-    /// Inserts a new record into the associated table
-    pub async fn insert<'a>(&mut self, conn: &Connection) -> Result<(), DbError> {
+    /// Inserts a new record into the associated table.
+    ///
+    /// The inserted row is read back, so keys generated by the database as well as changes
+    /// made by defaults or triggers are reflected on the entity.
+    ///
+    /// Unless keys are generated by the database, a record with the default key (such as a
+    /// natural key left unassigned) is rejected.
+    pub async fn insert(&mut self, conn: &Connection) -> Result<(), DbError> {
+        check_names::<T>()?;
+        let server_id = T::ID_STRATEGY == IdStrategy::Database;
+        if !server_id && self.id == T::Id::default() {
+            return Err(DbError::new(
+                &format!("{}: the key of the new record is not set", T::table_name()),
+                None,
+            ));
+        }
+        let id_params = if server_id { 0 } else { T::Id::COLUMNS };
         let prepared_s = conn
             .prepare(&format!(
-                "INSERT INTO  {table_name} (id, version, data) VALUES ($1, $2+1, $3)",
-                table_name = T::table_name()
+                "INSERT INTO  {table_name} ({id_columns}{version_column}, {data_column}) VALUES ({id_values}${version}+1, ${data}) RETURNING {returning}",
+                table_name = T::table_name(),
+                id_columns = if server_id {
+                    "".to_owned()
+                } else {
+                    format!("{}, ", dbkey::key_columns(T::ID_COLUMNS, None))
+                },
+                version_column = T::VERSION_COLUMN,
+                data_column = T::DATA_COLUMN,
+                id_values = (1..=id_params)
+                    .map(|i| format!("${}, ", i))
+                    .collect::<String>(),
+                version = id_params + 1,
+                data = id_params + 2,
+                returning = entity_columns::<T>(None),
             ))
             .await?;
-        conn.execute(
-            &prepared_s,
-            &[
-                &self.id,
-                &self.version,
-                &serde_json::to_value(&self.data).unwrap(),
-            ],
-        )
-        .await?;
-        self.version += 1;
-        self.data.set_id(self.id.clone());
-        self.data.set_version(self.version);
+        let data = serde_json::to_value(&self.data).unwrap();
+        let mut params = if server_id { vec![] } else { self.id.params() };
+        params.push(&self.version);
+        params.push(&data);
+        let row = conn.query_one(&prepared_s, &params).await?;
+        *self = Self::from_row(&row)?;
         Ok(())
     }
 
-    /// Persists the record
+    /// Persists the record.
+    ///
+    /// Nothing is written when the data did not change since it was loaded
+    /// (see [is_dirty](#method.is_dirty)), so the version is kept.
+    /// Otherwise the changes (see [diff](#method.diff)) are given to
+    /// [DbData::before_update](trait.DbData.html#method.before_update) first.
+    /// The updated row is read back, so changes made by triggers are reflected on the entity.
     pub async fn update(&mut self, conn: &Connection) -> Result<(), DbError> {
+        check_names::<T>()?;
+        let patch = self.diff()?;
+        if patch.is_empty() {
+            return Ok(());
+        }
+        self.data.before_update(&patch)?;
         let prepared_s = conn
             .prepare(&format!(
                 "UPDATE {table_name} SET
-            version=$2+1,
-            data=$3
+            {version_column}=${version}+1,
+            {data_column}=${data}
             WHERE
-            id = $1 AND
-            version = $2",
-                table_name = T::table_name()
+            {id_condition} AND
+            {version_column} = ${version}
+            RETURNING {returning}",
+                table_name = T::table_name(),
+                version_column = T::VERSION_COLUMN,
+                data_column = T::DATA_COLUMN,
+                id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+                version = T::Id::COLUMNS + 1,
+                data = T::Id::COLUMNS + 2,
+                returning = entity_columns::<T>(None),
             ))
             .await?;
-        let updated = conn
-            .execute(
-                &prepared_s,
-                &[
-                    &self.id,
-                    &self.version,
-                    &serde_json::to_value(&self.data).unwrap(),
-                ],
-            )
-            .await?
-            == 1;
-        if updated {
-            self.version += 1;
-            self.data.set_version(self.version);
-            Ok(())
-        } else {
-            Err(self.out_of_sync_err())
+        let data = serde_json::to_value(&self.data).unwrap();
+        let mut params = self.id.params();
+        params.push(&self.version);
+        params.push(&data);
+        match conn.query_opt(&prepared_s, &params).await? {
+            Some(row) => {
+                *self = Self::from_row(&row)?;
+                Ok(())
+            }
+            None => Err(self.out_of_sync_err()),
         }
     }
 
     /// Performs a record deletion
     pub async fn delete(&mut self, conn: &Connection) -> Result<(), DbError> {
+        check_names::<T>()?;
         let prepared_s = conn
             .prepare(&format!(
                 "DELETE FROM {table_name}
             WHERE
-            id = $1 AND
-            version = $2",
-                table_name = T::table_name()
+            {id_condition} AND
+            {version_column} = ${version}",
+                table_name = T::table_name(),
+                version_column = T::VERSION_COLUMN,
+                id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+                version = T::Id::COLUMNS + 1,
             ))
             .await?;
-        let deleted = conn
-            .execute(&prepared_s, &[&self.id, &self.version])
-            .await?
-            == 1;
+        let mut params = self.id.params();
+        params.push(&self.version);
+        let deleted = conn.execute(&prepared_s, &params).await? == 1;
         if deleted {
             self.version = 0;
             Ok(())
@@ -226,11 +549,12 @@ where
         conn: &Connection,
         filter: (&str, &[&(dyn ToSql + Sync)]),
     ) -> Result<Option<Self>, DbError> {
+        check_names::<T>()?;
         let prepared_s = conn
             .prepare(&format!(
                 "{select_part} WHERE {filter}",
                 select_part = T::select_part(),
-                filter = filter.0,
+                filter = promote_fields(filter.0, T::DATA_COLUMN, T::promoted_fields()),
             ))
             .await?;
 
@@ -238,8 +562,8 @@ where
         if result.is_empty() {
             Ok(None)
         } else {
-            let row = &result.get(0).unwrap();
-            DbEntity::from_row(&row).map(Some)
+            let row = &result.first().unwrap();
+            DbEntity::from_row(row).map(Some)
         }
     }
 
@@ -253,28 +577,30 @@ where
     ///    (
     ///        "data->>'user_name'=$1",
     ///        &["some_name"],
-    ///        Some(&["data->>'user_name' DESC"]),
+    ///        Some(&[Sort::desc("user_name")]),
     ///        0,
     ///        100,
     ///    ),
     /// );
     /// ```
+    #[allow(clippy::format_in_format_args)]
     pub async fn find_all(
         conn: &Connection,
         filter: Option<(&str, &[&(dyn ToSql + Sync)])>,
-        sorting: Option<&[&str]>,
+        sorting: Option<&[Sort]>,
         offset: i64,
         limit: i64,
     ) -> Result<Vec<Self>, DbError> {
+        check_names::<T>()?;
         let prepared_s = conn.prepare(&format!(
             "{select_part}{where}{sorting}{offset}{limit}",
             select_part = T::select_part(),
             where = match filter {
-                Some(where_clause) => format!(" WHERE {}",where_clause.0),
+                Some(where_clause) => format!(" WHERE {}", promote_fields(where_clause.0, T::DATA_COLUMN, T::promoted_fields())),
                 None => String::from(""),
             },
             sorting = match sorting {
-                Some(sorting_statement) => format!(" ORDER BY {}", make_sort_statement(sorting_statement, None) ),
+                Some(sorting_statement) => format!(" ORDER BY {}", promote_fields(&make_sort_statement(sorting_statement, T::DATA_COLUMN, None), T::DATA_COLUMN, T::promoted_fields())),
                 None => String::from("")
             },
             offset = format!(" OFFSET ${}", match filter {
@@ -305,6 +631,139 @@ where
         let result = conn.query(&prepared_s, &params[..]).await?;
         DbEntity::from_rows(&result)
     }
+
+    /// Searches all records whose document contains *partial* (`@>` operator, using GIN indexes
+    /// over the data column). Filter, sorting, offset and limit are the same as in find_all.
+    /// ## Example
+    /// ```ignore
+    /// DbEntity::<User>::find_containing(
+    ///    db_conn,
+    ///    &json!({"address": {"city": "Rome"}}),
+    ///    Some(("data->>'last_name' LIKE $1", &[&"R%"])),
+    ///    None,
+    ///    0,
+    ///    100,
+    /// );
+    /// ```
+    pub async fn find_containing<P>(
+        conn: &Connection,
+        partial: &P,
+        filter: Option<(&str, &[&(dyn ToSql + Sync)])>,
+        sorting: Option<&[Sort]>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, DbError>
+    where
+        P: Serialize,
+    {
+        let mut containing = Self::filter().contains(partial)?;
+        if let Some((filter, filter_val)) = filter {
+            containing = containing.and(filter, filter_val);
+        }
+        let params = containing.params();
+        Self::find_all(
+            conn,
+            Some((&containing.clause(), &params)),
+            sorting,
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    /// Searches all records matching the optional filter (as in find_all) and returns
+    /// a projection of each document decoded into *P*.
+    ///
+    /// *paths* are dot separated paths inside the document (such as "address.city"): each one
+    /// is projected on a key named after its last segment. "id" and "version" keys are always present.
+    /// Paths whose keys collide (such as "a.name" and "b.name", or "meta.id") are rejected.
+    /// ## Example
+    /// ```ignore
+    /// #[derive(Deserialize)]
+    /// struct UserCity {
+    ///     id: Uuid,
+    ///     user_name: String,
+    ///     city: Option<String>,
+    /// }
+    ///
+    /// DbEntity::<User>::select_as::<UserCity>(db_conn, &["user_name", "address.city"], None);
+    /// ```
+    pub async fn select_as<P>(
+        conn: &Connection,
+        paths: &[&str],
+        filter: Option<(&str, &[&(dyn ToSql + Sync)])>,
+    ) -> Result<Vec<P>, DbError>
+    where
+        P: DeserializeOwned,
+    {
+        check_names::<T>()?;
+        let filter_len = filter.map_or(0, |(_, filter_val)| filter_val.len());
+        let keys: Vec<&str> = paths
+            .iter()
+            .map(|path| path.rsplit('.').next().unwrap_or(path))
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            if *key == "id" || *key == "version" || keys[..i].contains(key) {
+                return Err(DbError::new(
+                    &format!("Duplicate projection key {}", key),
+                    None,
+                ));
+            }
+        }
+        let segments: Vec<Vec<&str>> = paths.iter().map(|path| path.split('.').collect()).collect();
+        let prepared_s = conn
+            .prepare(&format!(
+                "SELECT jsonb_build_object('id', {id}, 'version', {version_column}{paths}) FROM {table_name}{where}",
+                id = if T::ID_COLUMNS.len() == 1 {
+                    T::ID_COLUMNS[0].to_owned()
+                } else {
+                    format!("jsonb_build_array({})", dbkey::key_columns(T::ID_COLUMNS, None))
+                },
+                version_column = T::VERSION_COLUMN,
+                paths = (0..paths.len())
+                    .map(|i| format!(
+                        ", ${key}::text, {data_column} #> ${path}::text[]",
+                        key = filter_len + i * 2 + 1,
+                        data_column = T::DATA_COLUMN,
+                        path = filter_len + i * 2 + 2
+                    ))
+                    .collect::<String>(),
+                table_name = T::table_name(),
+                where = match filter {
+                    Some((filter, _)) => format!(
+                        " WHERE {}",
+                        promote_fields(filter, T::DATA_COLUMN, T::promoted_fields())
+                    ),
+                    None => String::from(""),
+                },
+            ))
+            .await?;
+
+        let mut params: Vec<&(dyn ToSql + Sync)> = match filter {
+            Some((_, filter_val)) => filter_val.to_vec(),
+            None => vec![],
+        };
+        for (key, path) in keys.iter().zip(segments.iter()) {
+            params.push(key);
+            params.push(path);
+        }
+        conn.query(&prepared_s, &params)
+            .await?
+            .iter()
+            .map(|row| serde_json::from_value(row.get(0)).map_err(DbError::from))
+            .collect()
+    }
+
+    /// Starts a filter over the data column of this table. See [Filter](struct.Filter.html).
+    pub fn filter<'a>() -> Filter<'a> {
+        Filter::new(T::DATA_COLUMN)
+    }
+
+    /// Starts an aggregation (count, sum, avg, min, max) over the documents of this table.
+    /// See [Aggregate](struct.Aggregate.html).
+    pub fn aggregate<'a>() -> Aggregate<'a, T> {
+        Aggregate::new()
+    }
 }
 
 impl<T> Deref for DbEntity<T>
@@ -321,6 +780,7 @@ where
 mod tests {
     use super::*;
     use serde_derive::*;
+    use uuid::Uuid;
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct User {
@@ -330,6 +790,8 @@ mod tests {
     }
 
     impl DbData for User {
+        type Id = Uuid;
+
         fn table_name() -> &'static str {
             "intrared.users"
         }
@@ -384,9 +846,11 @@ mod tests {
     #[test]
     fn test_select_extra_columns() {
         #[derive(Serialize, Deserialize)]
-        struct Test {};
+        struct Test {}
 
         impl DbData for Test {
+            type Id = Uuid;
+
             fn table_name() -> &'static str {
                 "intrared.test"
             }
@@ -408,9 +872,85 @@ mod tests {
             }
             fn set_id(&mut self, _uuid: Uuid) {}
             fn set_version(&mut self, _version: i32) {}
-        };
+        }
         let t = Test {};
 
         assert_eq!(Test::select_part(), t.select_part1());
     }
+
+    #[test]
+    fn test_promote_fields() {
+        let promoted = [PromotedField {
+            field: "email",
+            column: "email",
+            unique: true,
+        }];
+
+        assert_eq!(
+            promote_fields(
+                "data->>'email' = $1 AND data->>'name' = $2",
+                "data",
+                &promoted
+            ),
+            "email = $1 AND data->>'name' = $2"
+        );
+        assert_eq!(
+            promote_fields("a.doc->>'email' DESC", "doc", &promoted),
+            "a.email DESC"
+        );
+        assert_eq!(
+            promote_fields("mydata->>'email' = $1", "data", &promoted),
+            "mydata->>'email' = $1"
+        );
+        assert_eq!(
+            promote_fields(
+                "data->>'email' <> 'data->>''email''' AND \"data->>'email'\" = $1 \
+                 /* data->>'email' */ -- data->>'email'\nOR data->>'email' IS NULL",
+                "data",
+                &promoted
+            ),
+            "email <> 'data->>''email''' AND \"data->>'email'\" = $1 \
+             /* data->>'email' */ -- data->>'email'\nOR email IS NULL"
+        );
+    }
+
+    #[test]
+    fn test_promoted_fields_ddl() {
+        #[derive(Serialize, Deserialize)]
+        struct Test {}
+
+        impl DbData for Test {
+            type Id = Uuid;
+
+            fn table_name() -> &'static str {
+                "intrared.test"
+            }
+
+            fn promoted_fields() -> &'static [PromotedField] {
+                &[PromotedField {
+                    field: "name",
+                    column: "name_col",
+                    unique: false,
+                }]
+            }
+
+            fn id(&self) -> Option<Uuid> {
+                None
+            }
+
+            fn version(&self) -> Option<i32> {
+                None
+            }
+            fn set_id(&mut self, _uuid: Uuid) {}
+            fn set_version(&mut self, _version: i32) {}
+        }
+
+        assert_eq!(
+            promoted_fields_ddl::<Test>(),
+            vec![
+                "ALTER TABLE intrared.test ADD COLUMN IF NOT EXISTS name_col text GENERATED ALWAYS AS (data->>'name') STORED",
+                "CREATE INDEX IF NOT EXISTS test_name_col_ix ON intrared.test (name_col)",
+            ]
+        );
+    }
 }
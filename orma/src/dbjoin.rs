@@ -2,20 +2,23 @@ use crate::db_anti_corruption::Connection;
 use crate::*;
 
 use tokio_postgres::types::ToSql;
-use uuid::Uuid;
 
 /// This struct is used to create a join between DbEntyty elements
 /// and JoinBuilder is used to create a DbJoin instance.
 ///
-/// *sorting* parameter (such as vec!["data->>'first_name'", "data->>'last_name' DESC"]) take effect after a
+/// *sorting* parameter (such as vec![Sort::asc("first_name"), Sort::desc("last_name")]) take effect after a
 /// call to *fetch* method.
 ///
 /// *items* can be persisted to db after a call to *save_items* method.
 ///
 /// See [JoinBuilder](struct.JoinBuilder.html) for more info
-pub struct DbJoin {
+pub struct DbJoin<K = Uuid>
+where
+    K: DbKey,
+{
     source_table: String,
-    source_id: Uuid,
+    source_id: K,
+    source_id_columns: Vec<String>,
     source_fk: String,
     target_table: String,
     join_table: Option<String>,
@@ -26,177 +29,113 @@ pub struct DbJoin {
     pub limit: i64,
     /// Used to provide a sorting method on data fetching.
     /// ```rust
-    /// vec!["data->>'first_name'", "data->>'last_name' DESC"];
+    /// # use orma::{Cast, Sort};
+    /// vec![Sort::asc("first_name"), Sort::desc("age").cast(Cast::Integer)];
     /// ```
-    pub sorting: Vec<String>,
+    pub sorting: Vec<Sort>,
 }
 
-impl DbJoin {
-    async fn load_items_simple_join<A>(
-        &self,
-        conn: &Connection,
-        filter: Option<(&str, &[&(dyn ToSql + Sync)])>,
-    ) -> Result<Vec<DbEntity<A>>, DbError>
+impl<K> DbJoin<K>
+where
+    K: DbKey,
+{
+    fn source_id_columns(&self) -> Vec<&str> {
+        self.source_id_columns.iter().map(String::as_str).collect()
+    }
+
+    fn simple_join_condition(&self) -> String {
+        format!(
+            "{source_table} b WHERE {b_join}",
+            source_table = &self.source_table,
+            b_join = dbkey::key_join(
+                &self.source_id_columns(),
+                Some("b"),
+                &dbkey::split_columns(&self.source_fk),
+                Some("a")
+            ),
+        )
+    }
+
+    fn table_join_condition<A>(&self) -> String
     where
         A: DbData,
     {
-        let qry = format!(
-            "{A}, {source_table} b WHERE b.id = a.{b_fk}
-            {filter}
-            AND b.id = ${id_index}
-            {order_by}{offset}{limit}",
-            A = dbentity::select_part(&self.target_table, false, Some("a")),
-            source_table = &self.source_table,
-            b_fk = &self.source_fk,
-            filter = match filter {
-                Some((filter, _)) => format!("AND {}", filter.to_string()),
-                _ => "".to_string(),
-            },
-            id_index = match filter {
-                Some((_, filter)) => filter.len() + 1,
-                _ => 1,
-            },
-            order_by = if !self.sorting.is_empty() {
-                let order_by: Vec<&str> = self.sorting.iter().map(|x| x.as_ref()).collect();
-                format!(
-                    " ORDER BY {}",
-                    dbentity::make_sort_statement(&order_by[..], Some("a"))
-                )
-            } else {
-                "".to_string()
-            },
-            offset = format!(
-                " OFFSET ${}",
-                match filter {
-                    Some(filter) => filter.1.len() + 2,
-                    None => 2,
-                }
+        format!(
+            "{join_table} ab, {source_table} b WHERE {a_join} AND {b_join}",
+            join_table = self.join_table.as_ref().unwrap(),
+            source_table = self.source_table,
+            a_join = dbkey::key_join(
+                A::ID_COLUMNS,
+                Some("a"),
+                &dbkey::split_columns(self.items_fk.as_ref().unwrap()),
+                Some("ab")
             ),
-            limit = if self.limit < 0 {
-                "".to_string()
-            } else {
-                format!(
-                    " LIMIT ${}",
-                    match filter {
-                        Some(filter) => filter.1.len() + 3,
-                        None => 1,
-                    }
-                )
-            },
-        );
-        let p_statement = conn.prepare(&qry).await?;
-        let id = self.source_id;
-        match filter {
-            Some((_, filter_val)) => {
-                let mut filter = filter_val.to_vec();
-                let filter_id: Vec<&(dyn ToSql + Sync)> = vec![&id];
-                filter.extend(filter_id.iter());
-                filter.push(&self.offset as &(dyn ToSql + Sync));
-                if self.limit >= 0 {
-                    filter.push(&self.limit as &(dyn ToSql + Sync));
-                }
-                let result = conn.query(&p_statement, &filter).await?;
-                Ok(DbEntity::from_rows(&result)?)
-            }
-            _ => {
-                let filter: Vec<&(dyn ToSql + Sync)> = if self.limit < 0 {
-                    vec![&id, &self.offset as &(dyn ToSql + Sync)]
-                } else {
-                    vec![
-                        &id,
-                        &self.offset as &(dyn ToSql + Sync),
-                        &self.limit as &(dyn ToSql + Sync),
-                    ]
-                };
-                let result = conn.query(&p_statement, &filter).await?;
-                Ok(DbEntity::from_rows(&result)?)
-            }
-        }
+            b_join = dbkey::key_join(
+                &self.source_id_columns(),
+                Some("b"),
+                &dbkey::split_columns(&self.source_fk),
+                Some("ab")
+            ),
+        )
     }
 
-    async fn load_items_table_join<A>(
+    async fn load_items<A>(
         &self,
         conn: &Connection,
+        join_condition: String,
         filter: Option<(&str, &[&(dyn ToSql + Sync)])>,
     ) -> Result<Vec<DbEntity<A>>, DbError>
     where
         A: DbData,
     {
+        let filter_len = filter.map_or(0, |(_, filter_val)| filter_val.len());
         let qry = format!(
-            "{A}, {join_table} ab, {source_table} b WHERE a.id = ab.{a_fk} AND b.id = ab.{b_fk}
-                        {filter}
-                        AND b.id = ${id_index}
-                        {order_by}{offset}{limit}",
-            A = dbentity::select_part(&self.target_table, false, Some("a")),
-            join_table = self.join_table.as_ref().unwrap(),
-            source_table = self.source_table,
-            a_fk = self.items_fk.as_ref().unwrap(),
-            b_fk = self.source_fk,
+            "{A}, {join_condition}
+            {filter}
+            AND {id_condition}
+            {order_by} OFFSET ${offset}{limit}",
+            A = dbentity::select_part_for::<A>(&self.target_table, false, Some("a")),
+            join_condition = join_condition,
             filter = match filter {
-                Some((filter, _)) => format!("AND {}", filter.to_string()),
+                Some((filter, _)) => format!(
+                    "AND {}",
+                    dbentity::promote_fields(filter, A::DATA_COLUMN, A::promoted_fields())
+                ),
                 _ => "".to_string(),
             },
-            id_index = match filter {
-                Some((_, filter)) => filter.len() + 1,
-                _ => 1,
-            },
+            id_condition =
+                dbkey::key_condition(&self.source_id_columns(), Some("b"), filter_len + 1),
             order_by = if !self.sorting.is_empty() {
-                let order_by: Vec<&str> = self.sorting.iter().map(|x| x.as_ref()).collect();
                 format!(
                     " ORDER BY {}",
-                    dbentity::make_sort_statement(&order_by[..], Some("a"))
+                    dbentity::promote_fields(
+                        &dbentity::make_sort_statement(&self.sorting, A::DATA_COLUMN, Some("a")),
+                        A::DATA_COLUMN,
+                        A::promoted_fields()
+                    )
                 )
             } else {
                 "".to_string()
             },
-            offset = format!(
-                " OFFSET ${}",
-                match filter {
-                    Some(filter) => filter.1.len() + 2,
-                    None => 2,
-                }
-            ),
+            offset = filter_len + K::COLUMNS + 1,
             limit = if self.limit < 0 {
                 "".to_string()
             } else {
-                format!(
-                    " LIMIT ${}",
-                    match filter {
-                        Some(filter) => filter.1.len() + 3,
-                        None => 1,
-                    }
-                )
+                format!(" LIMIT ${}", filter_len + K::COLUMNS + 2)
             },
         );
-
         let p_statement = conn.prepare(&qry).await?;
-        let id = self.source_id;
-        match filter {
-            Some((_, filter_val)) => {
-                let mut filter = filter_val.to_vec();
-                let filter_id: Vec<&(dyn ToSql + Sync)> = vec![&id];
-                filter.extend(filter_id.iter());
-                filter.push(&self.offset as &(dyn ToSql + Sync));
-                if self.limit >= 0 {
-                    filter.push(&self.limit as &(dyn ToSql + Sync));
-                }
-                let result = conn.query(&p_statement, &filter).await?;
-                Ok(DbEntity::from_rows(&result)?)
-            }
-            _ => {
-                let filter: Vec<&(dyn ToSql + Sync)> = if self.limit < 0 {
-                    vec![&id, &self.offset as &(dyn ToSql + Sync)]
-                } else {
-                    vec![
-                        &id,
-                        &self.offset as &(dyn ToSql + Sync),
-                        &self.limit as &(dyn ToSql + Sync),
-                    ]
-                };
-                let result = conn.query(&p_statement, &filter).await?;
-                Ok(DbEntity::from_rows(&result)?)
-            }
+        let mut params: Vec<&(dyn ToSql + Sync)> = match filter {
+            Some((_, filter_val)) => filter_val.to_vec(),
+            _ => vec![],
+        };
+        params.extend(self.source_id.params());
+        params.push(&self.offset);
+        if self.limit >= 0 {
+            params.push(&self.limit);
         }
+        let result = conn.query(&p_statement, &params).await?;
+        DbEntity::from_rows(&result)
     }
 
     /// This method fetches items field for the given join using the current sorting field.
@@ -205,8 +144,14 @@ impl DbJoin {
         A: DbData,
     {
         let items = match (self.join_table.as_ref(), self.items_fk.as_ref()) {
-            (Some(_join_table), Some(_items_fk)) => self.load_items_table_join(conn, None).await?,
-            _ => self.load_items_simple_join(conn, None).await?,
+            (Some(_join_table), Some(_items_fk)) => {
+                self.load_items(conn, self.table_join_condition::<A>(), None)
+                    .await?
+            }
+            _ => {
+                self.load_items(conn, self.simple_join_condition(), None)
+                    .await?
+            }
         };
         Ok(items)
     }
@@ -224,143 +169,171 @@ impl DbJoin {
     {
         let items = match (self.join_table.as_ref(), self.items_fk.as_ref()) {
             (Some(_join_table), Some(_items_fk)) => {
-                self.load_items_table_join(conn, Some(filter)).await?
+                self.load_items(conn, self.table_join_condition::<A>(), Some(filter))
+                    .await?
+            }
+            _ => {
+                self.load_items(conn, self.simple_join_condition(), Some(filter))
+                    .await?
             }
-            _ => self.load_items_simple_join(conn, Some(filter)).await?,
         };
         Ok(items)
     }
 
-    async fn remove_items_table_join_by_id(
+    async fn remove_items_table_join_by_id<B>(
         &self,
         conn: &Connection,
-        items_id: Option<&[Uuid]>,
-    ) -> Result<(), DbError> {
-        if items_id.is_some() {
-            let delete_sql = format!(
-                "DELETE FROM {join_table} WHERE {items_fk} = ANY($1)",
-                join_table = self
-                    .join_table
-                    .as_ref()
-                    .ok_or_else(|| DbError::new("No join_table defined", None))?,
-                items_fk = self
-                    .items_fk
+        items_id: Option<&[B::Id]>,
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        let join_table = self
+            .join_table
+            .as_ref()
+            .ok_or_else(|| DbError::new("No join_table defined", None))?;
+        let source_fk = dbkey::split_columns(&self.source_fk);
+        let mut params = self.source_id.params();
+        let delete_sql = if let Some(items_id) = items_id {
+            let items_fk = dbkey::split_columns(
+                self.items_fk
                     .as_ref()
                     .ok_or_else(|| DbError::new("No items_fk defined", None))?,
             );
-            let delete_qry = conn.prepare(&delete_sql).await?;
-            conn.execute(&delete_qry, &[&items_id]).await?;
+            params.extend(items_id.iter().flat_map(DbKey::params));
+            format!(
+                "DELETE FROM {join_table} WHERE {source_condition} AND {items_condition}",
+                join_table = join_table,
+                source_condition = dbkey::key_condition(&source_fk, None, 1),
+                items_condition = dbkey::key_in(&items_fk, None, K::COLUMNS + 1, items_id.len()),
+            )
         } else {
-            let delete_sql = format!(
-                "DELETE FROM {join_table} WHERE {source_fk} = $1",
-                join_table = self
-                    .join_table
-                    .as_ref()
-                    .ok_or_else(|| DbError::new("No join_table defined", None))?,
-                source_fk = self.source_fk
-            );
-            let delete_qry = conn.prepare(&delete_sql).await?;
-            let id = self.source_id;
-            conn.execute(&delete_qry, &[&id]).await?;
+            format!(
+                "DELETE FROM {join_table} WHERE {source_condition}",
+                join_table = join_table,
+                source_condition = dbkey::key_condition(&source_fk, None, 1),
+            )
         };
+        let delete_qry = conn.prepare(&delete_sql).await?;
+        conn.execute(&delete_qry, &params).await?;
 
         Ok(())
     }
 
-    async fn add_items_table_join_by_id(
+    async fn add_items_table_join_by_id<B>(
         &self,
         conn: &Connection,
-        items_id: &[&Uuid],
-    ) -> Result<(), DbError> {
+        items_id: &[&B::Id],
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        let columns = K::COLUMNS + B::Id::COLUMNS;
         let insert_val_sql = format!(
             "INSERT INTO {table_name} ({a_fk}, {b_fk}) VALUES {values} ON CONFLICT DO NOTHING",
             table_name = self
                 .join_table
                 .as_ref()
-                .ok_or_else(|| DbError::new("No join_table defined", None))?
-                .to_string(),
-            b_fk = self
-                .items_fk
-                .as_ref()
-                .ok_or_else(|| DbError::new("No items_fk defined", None))?,
-            a_fk = self.source_fk,
+                .ok_or_else(|| DbError::new("No join_table defined", None))?,
+            b_fk = dbkey::key_columns(
+                &dbkey::split_columns(
+                    self.items_fk
+                        .as_ref()
+                        .ok_or_else(|| DbError::new("No items_fk defined", None))?
+                ),
+                None
+            ),
+            a_fk = dbkey::key_columns(&dbkey::split_columns(&self.source_fk), None),
             values = (0..items_id.len())
-                .map(|i| format!("(${}, ${})", (i * 2) + 1, (i * 2) + 2))
+                .map(|i| format!(
+                    "({})",
+                    (1..=columns)
+                        .map(|c| format!("${}", i * columns + c))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ))
                 .collect::<Vec<String>>()
                 .join(", ")
         );
 
         let insert_val_qry = conn.prepare(&insert_val_sql).await?;
 
-        let ids_to_add: Vec<Vec<Uuid>> = items_id
-            .to_vec()
-            .into_iter()
-            .map(|item_id| -> Vec<Uuid> {
-                let source_id = self.source_id;
-                let item_id = item_id.clone();
-                vec![source_id, item_id]
-            })
-            .collect::<Vec<Vec<Uuid>>>();
-
-        let ids_to_add = ids_to_add
+        let ids_to_add = items_id
             .iter()
-            .flatten()
-            .map(|item| item as &(dyn ToSql + Sync))
+            .flat_map(|&item_id| [self.source_id.params(), item_id.params()].concat())
             .collect::<Vec<&(dyn ToSql + Sync)>>();
 
         conn.execute(&insert_val_qry, &ids_to_add).await?;
         Ok(())
     }
 
-    async fn remove_items_simple_join_by_id(
+    async fn remove_items_simple_join_by_id<B>(
         &self,
         conn: &Connection,
-        items_id: Option<&[Uuid]>,
-    ) -> Result<(), DbError> {
-        let id = self.source_id;
-        if items_id.is_some() {
-            let delete_sql = format!(
-                "UPDATE FROM {target_table} SET {a_fk} = NULL WHERE {a_fk} = $1 AND id = ANY($2)",
+        items_id: Option<&[B::Id]>,
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        let source_fk = dbkey::split_columns(&self.source_fk);
+        let mut params = self.source_id.params();
+        let set_null = source_fk
+            .iter()
+            .map(|fk| format!("{} = NULL", fk))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let delete_sql = if let Some(items_id) = items_id {
+            params.extend(items_id.iter().flat_map(DbKey::params));
+            format!(
+                "UPDATE {target_table} SET {set_null} WHERE {source_condition} AND {items_condition}",
                 target_table = self.target_table,
-                a_fk = self.source_fk
-            );
-            let delete_qry = conn.prepare(&delete_sql).await?;
-
-            conn.execute(&delete_qry, &[&id, &items_id]).await?;
+                set_null = set_null,
+                source_condition = dbkey::key_condition(&source_fk, None, 1),
+                items_condition = dbkey::key_in(B::ID_COLUMNS, None, K::COLUMNS + 1, items_id.len()),
+            )
         } else {
-            let delete_sql = format!(
-                "UPDATE FROM {target_table} SET {a_fk} = NULL WHERE {a_fk} = $1",
+            format!(
+                "UPDATE {target_table} SET {set_null} WHERE {source_condition}",
                 target_table = self.target_table,
-                a_fk = self.source_fk
-            );
-            let delete_qry = conn.prepare(&delete_sql).await?;
-            conn.execute(&delete_qry, &[&id]).await?;
+                set_null = set_null,
+                source_condition = dbkey::key_condition(&source_fk, None, 1),
+            )
         };
+        let delete_qry = conn.prepare(&delete_sql).await?;
+        conn.execute(&delete_qry, &params).await?;
 
         Ok(())
     }
 
-    async fn add_items_simple_join_by_id(
+    async fn add_items_simple_join_by_id<B>(
         &self,
         conn: &Connection,
-        items_id: &[&Uuid],
-    ) -> Result<(), DbError> {
+        items_id: &[&B::Id],
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
         let update_sql = format!(
-            "UPDATE FROM {target_table} SET {a_fk} = $1 WHERE id =  ANY($2)",
+            "UPDATE {target_table} SET {set_fk} WHERE {items_condition}",
             target_table = self.target_table,
-            a_fk = self.source_fk
+            set_fk = dbkey::key_assignments(&dbkey::split_columns(&self.source_fk), 1),
+            items_condition = dbkey::key_in(B::ID_COLUMNS, None, K::COLUMNS + 1, items_id.len()),
         );
 
         let update_qry = conn.prepare(&update_sql).await?;
 
-        let id = self.source_id;
+        let mut params = self.source_id.params();
+        params.extend(items_id.iter().flat_map(|&item_id| item_id.params()));
 
-        conn.execute(&update_qry, &[&id, &items_id]).await?;
+        conn.execute(&update_qry, &params).await?;
 
         Ok(())
     }
 
     /// This method adds items for the given join to the DB.
+    ///
+    /// No transaction is opened: use [Connection::in_transaction](struct.Connection.html#method.in_transaction)
+    /// to make it atomic along with other changes.
     pub async fn add_items<A>(
         &self,
         conn: &mut Connection,
@@ -369,40 +342,47 @@ impl DbJoin {
     where
         A: DbData,
     {
-        let ids_to_add: Vec<Uuid> = items
+        let ids_to_add: Vec<A::Id> = items
             .iter()
-            .map(|item| -> Result<Uuid, DbError> {
+            .map(|item| -> Result<A::Id, DbError> {
                 item.id()
                     .ok_or_else(|| DbError::new("Item not persisted. No ID defined!", None))
             })
-            .collect::<Result<Vec<Uuid>, DbError>>()?;
+            .collect::<Result<Vec<A::Id>, DbError>>()?;
 
-        self.add_items_by_id(conn, &ids_to_add.iter().collect::<Vec<&Uuid>>())
+        self.add_items_by_id::<A>(conn, &ids_to_add.iter().collect::<Vec<&A::Id>>())
             .await
     }
 
     /// This method adds items for the given join to the DB using object ids.
-    pub async fn add_items_by_id(
+    ///
+    /// *B* is the DbData type of the items.
+    pub async fn add_items_by_id<B>(
         &self,
         conn: &mut Connection,
-        items: &[&Uuid],
-    ) -> Result<(), DbError> {
-        let result = async {
-            match (self.join_table.as_ref(), self.items_fk.as_ref()) {
-                (Some(_), Some(_)) => {
-                    self.add_items_table_join_by_id(conn, &items).await?;
-                }
-                _ => {
-                    self.add_items_simple_join_by_id(conn, &items).await?;
-                }
-            };
-            Ok(())
+        items: &[&B::Id],
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        if items.is_empty() {
+            return Ok(());
         }
-        .await;
-        if result.is_err() {
-            conn.rollback().await?;
+        self.insert_join_rows::<B>(conn, items).await
+    }
+
+    pub(crate) async fn insert_join_rows<B>(
+        &self,
+        conn: &Connection,
+        items: &[&B::Id],
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        match (self.join_table.as_ref(), self.items_fk.as_ref()) {
+            (Some(_), Some(_)) => self.add_items_table_join_by_id::<B>(conn, items).await,
+            _ => self.add_items_simple_join_by_id::<B>(conn, items).await,
         }
-        result
     }
 
     /// This method removes items for the given join to the DB.
@@ -415,34 +395,55 @@ impl DbJoin {
         A: DbData,
     {
         if let Some(items) = items {
-            let ids_to_remove: Vec<Uuid> = items
-                .into_iter()
-                .map(|item| -> Result<Uuid, DbError> {
+            let ids_to_remove: Vec<A::Id> = items
+                .iter()
+                .map(|item| -> Result<A::Id, DbError> {
                     item.id()
                         .ok_or_else(|| DbError::new("Item not persisted. No ID defined!", None))
                 })
-                .collect::<Result<Vec<Uuid>, DbError>>()?;
-            self.remove_items_by_id(conn, Some(&ids_to_remove)).await
+                .collect::<Result<Vec<A::Id>, DbError>>()?;
+            self.remove_items_by_id::<A>(conn, Some(&ids_to_remove))
+                .await
         } else {
-            self.remove_items_by_id(conn, None).await
+            self.remove_items_by_id::<A>(conn, None).await
         }
     }
 
     /// This method removes items for the given join to the DB using object ids.
-    pub async fn remove_items_by_id(
+    ///
+    /// *B* is the DbData type of the items.
+    pub async fn remove_items_by_id<B>(
         &self,
         conn: &mut Connection,
-        items_id: Option<&[Uuid]>,
-    ) -> Result<(), DbError> {
+        items_id: Option<&[B::Id]>,
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
+        if items_id.is_some_and(<[B::Id]>::is_empty) {
+            return Ok(());
+        }
+        self.delete_join_rows::<B>(conn, items_id).await
+    }
+
+    pub(crate) async fn delete_join_rows<B>(
+        &self,
+        conn: &Connection,
+        items_id: Option<&[B::Id]>,
+    ) -> Result<(), DbError>
+    where
+        B: DbData,
+    {
         match (self.join_table.as_ref(), self.items_fk.as_ref()) {
             (Some(_), Some(_)) => {
-                self.remove_items_table_join_by_id(conn, items_id).await?;
+                self.remove_items_table_join_by_id::<B>(conn, items_id)
+                    .await
             }
             _ => {
-                self.remove_items_simple_join_by_id(conn, items_id).await?;
+                self.remove_items_simple_join_by_id::<B>(conn, items_id)
+                    .await
             }
-        };
-        Ok(())
+        }
     }
 }
 
@@ -455,7 +456,7 @@ where
     target_table: Option<&'a str>,
     join_table: Option<&'a str>,
     items_fk: Option<&'a str>,
-    sorting: &'a [&'a str],
+    sorting: &'a [Sort],
 }
 
 impl<'a, A> JoinBuilder<'a, A>
@@ -503,33 +504,45 @@ where
 
     /// DbJoin sorting attribute used to provide a sorting method on data fetching.
     /// ```rust
-    /// vec!["data->>'first_name'", "data->>'last_name' DESC"];
+    /// # use orma::{Cast, Sort};
+    /// vec![Sort::asc("first_name"), Sort::desc("age").cast(Cast::Integer)];
     /// ```
-    pub fn with_sorting(mut self, sorting: &'a [&'a str]) -> Self {
+    pub fn with_sorting(mut self, sorting: &'a [Sort]) -> Self {
         self.sorting = sorting;
         self
     }
 
-    /// Creates the DbJoin object and fetches the data
-    pub fn build(&self) -> Result<DbJoin, DbError> {
+    /// Creates the DbJoin object and fetches the data.
+    ///
+    /// Table and column names are checked with [check_identifier](fn.check_identifier.html).
+    pub fn build(&self) -> Result<DbJoin<A::Id>, DbError> {
+        dbentity::check_names::<A>()?;
+        for table in self.target_table.iter().chain(self.join_table.iter()) {
+            check_identifier(table)?;
+        }
+        for columns in self.source_fk.iter().chain(self.items_fk.iter()) {
+            for column in dbkey::split_columns(columns) {
+                check_identifier(column)?;
+            }
+        }
         Ok(DbJoin {
             source_table: A::table_name().to_string(),
+            source_id_columns: A::ID_COLUMNS.iter().map(|&c| c.to_string()).collect(),
             source_id: self
                 .source
                 .id()
                 .ok_or_else(|| DbError::new("Source entity has no ID", None))?,
-            source_fk: self.source_fk.expect("no source_fk defined").to_string(),
+            source_fk: self
+                .source_fk
+                .ok_or_else(|| DbError::new("Source entity has no source_fk", None))?
+                .to_string(),
             target_table: self
                 .target_table
                 .ok_or_else(|| DbError::new("Source entity has no target_table", None))?
                 .to_string(),
             join_table: self.join_table.map(String::from),
             items_fk: self.items_fk.map(String::from),
-            sorting: self
-                .sorting
-                .iter()
-                .map(|&item| item.to_string())
-                .collect::<Vec<String>>(),
+            sorting: self.sorting.to_vec(),
             offset: 0,
             limit: -1,
         })
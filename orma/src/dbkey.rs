@@ -0,0 +1,317 @@
+use crate::{Row, ToSql};
+
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// How new keys are generated for a DbData type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdStrategy {
+    /// Random keys (UUIDv4)
+    Random,
+    /// Time-ordered keys (UUIDv7), friendly to btree indexes
+    TimeOrdered,
+    /// ULID-compatible keys: a 48 bit millisecond timestamp followed by 80 random bits
+    Ulid,
+    /// Keys are generated by the database (using column defaults or identities)
+    /// and read back after the record is inserted
+    Database,
+}
+
+fn current_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn millis_bytes(millis: u64) -> [u8; 6] {
+    let bytes = millis.to_be_bytes();
+    [bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]
+}
+
+fn unix_millis() -> [u8; 6] {
+    millis_bytes(current_millis())
+}
+
+/// Timestamp and 12 bit sequence of the last UUIDv7 created by this process
+static LAST_V7: Mutex<(u64, u16)> = Mutex::new((0, 0));
+
+/// Creates a time-ordered UUIDv7.
+///
+/// Within a millisecond the 12 bits following the timestamp are a counter, started from a
+/// random value in the lower half of its range, so the keys created by this process are
+/// strictly increasing (the timestamp is moved forward when the counter overflows or the
+/// clock goes back).
+pub fn uuid_v7() -> Uuid {
+    let mut bytes = *Uuid::new_v4().as_bytes();
+    let (millis, counter) = {
+        let mut last = LAST_V7.lock().unwrap_or_else(|e| e.into_inner());
+        let now = current_millis();
+        *last = if now > last.0 {
+            (now, u16::from_be_bytes([bytes[6], bytes[7]]) & 0x07FF)
+        } else if last.1 < 0x0FFF {
+            (last.0, last.1 + 1)
+        } else {
+            (
+                last.0 + 1,
+                u16::from_be_bytes([bytes[6], bytes[7]]) & 0x07FF,
+            )
+        };
+        *last
+    };
+    bytes[..6].copy_from_slice(&millis_bytes(millis));
+    bytes[6..8].copy_from_slice(&(0x7000 | counter).to_be_bytes());
+    bytes[8] = 0x80 | (bytes[8] & 0x3F);
+    Uuid::from_bytes(bytes)
+}
+
+/// Creates a UUID holding a ULID (48 bit millisecond timestamp and 80 random bits)
+pub fn uuid_ulid() -> Uuid {
+    // version and variant bits of UUIDv4 are not random, so only the first 6 bytes
+    // of a random UUID are used
+    let random_a = Uuid::new_v4();
+    let random_b = Uuid::new_v4();
+    let mut bytes = [0u8; 16];
+    bytes[..6].copy_from_slice(&unix_millis());
+    bytes[6..12].copy_from_slice(&random_a.as_bytes()[..6]);
+    bytes[12..].copy_from_slice(&random_b.as_bytes()[..4]);
+    Uuid::from_bytes(bytes)
+}
+
+/// This trait maps the primary key of a data table.
+///
+/// A key can span more than one column (composite keys are mapped as tuples), so
+/// each key is bound as many query parameters as its columns.
+pub trait DbKey: Clone + Debug + Default + PartialEq + Send + Sync + 'static {
+    /// Number of columns the key is mapped to
+    const COLUMNS: usize;
+
+    /// Query parameters, one for each key column
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)>;
+
+    /// Reads the key from a database row starting at column *idx*
+    fn from_row(row: &Row, idx: usize) -> Self;
+
+    /// Generates a new key on client side following *strategy*. Keys that cannot be generated
+    /// (such as natural keys or keys generated by the database) return None.
+    fn generate(_strategy: IdStrategy) -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! scalar_key {
+    ($($key_type:ty),*) => {
+        $(
+            impl DbKey for $key_type {
+                const COLUMNS: usize = 1;
+
+                fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+                    vec![self]
+                }
+
+                fn from_row(row: &Row, idx: usize) -> Self {
+                    row.get(idx)
+                }
+            }
+        )*
+    };
+}
+
+scalar_key!(i32, i64, String);
+
+impl DbKey for Uuid {
+    const COLUMNS: usize = 1;
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![self]
+    }
+
+    fn from_row(row: &Row, idx: usize) -> Self {
+        row.get(idx)
+    }
+
+    fn generate(strategy: IdStrategy) -> Option<Self> {
+        match strategy {
+            IdStrategy::Random => Some(Uuid::new_v4()),
+            IdStrategy::TimeOrdered => Some(uuid_v7()),
+            IdStrategy::Ulid => Some(uuid_ulid()),
+            IdStrategy::Database => None,
+        }
+    }
+}
+
+impl<A, B> DbKey for (A, B)
+where
+    A: DbKey,
+    B: DbKey,
+{
+    const COLUMNS: usize = A::COLUMNS + B::COLUMNS;
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        [self.0.params(), self.1.params()].concat()
+    }
+
+    fn from_row(row: &Row, idx: usize) -> Self {
+        (A::from_row(row, idx), B::from_row(row, idx + A::COLUMNS))
+    }
+}
+
+impl<A, B, C> DbKey for (A, B, C)
+where
+    A: DbKey,
+    B: DbKey,
+    C: DbKey,
+{
+    const COLUMNS: usize = A::COLUMNS + B::COLUMNS + C::COLUMNS;
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        [self.0.params(), self.1.params(), self.2.params()].concat()
+    }
+
+    fn from_row(row: &Row, idx: usize) -> Self {
+        (
+            A::from_row(row, idx),
+            B::from_row(row, idx + A::COLUMNS),
+            C::from_row(row, idx + A::COLUMNS + B::COLUMNS),
+        )
+    }
+}
+
+/// Splits a comma separated list of column names (as used for composite foreign keys)
+pub(crate) fn split_columns(columns: &str) -> Vec<&str> {
+    columns.split(',').map(str::trim).collect()
+}
+
+fn aliased(column: &str, alias: Option<&str>) -> String {
+    match alias {
+        Some(alias) => format!("{}.{}", alias, column),
+        None => column.to_owned(),
+    }
+}
+
+/// Comma separated list of key columns, optionally prefixed with *alias*
+pub(crate) fn key_columns(columns: &[&str], alias: Option<&str>) -> String {
+    columns
+        .iter()
+        .map(|column| aliased(column, alias))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn key_params(columns: &[&str], alias: Option<&str>, first_param: usize) -> Vec<String> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{} = ${}", aliased(column, alias), first_param + i))
+        .collect()
+}
+
+/// Condition matching the key columns with positional parameters starting from *first_param*
+pub(crate) fn key_condition(columns: &[&str], alias: Option<&str>, first_param: usize) -> String {
+    key_params(columns, alias, first_param).join(" AND ")
+}
+
+/// Assignments (as in UPDATE ... SET) of the key columns to positional parameters starting
+/// from *first_param*
+pub(crate) fn key_assignments(columns: &[&str], first_param: usize) -> String {
+    key_params(columns, None, first_param).join(", ")
+}
+
+/// Condition joining two sets of key columns
+pub(crate) fn key_join(
+    left: &[&str],
+    left_alias: Option<&str>,
+    right: &[&str],
+    right_alias: Option<&str>,
+) -> String {
+    left.iter()
+        .zip(right.iter())
+        .map(|(l, r)| format!("{} = {}", aliased(l, left_alias), aliased(r, right_alias)))
+        .collect::<Vec<String>>()
+        .join(" AND ")
+}
+
+/// Condition matching the key columns against *keys* key values, whose parameters start
+/// from *first_param*
+pub(crate) fn key_in(
+    columns: &[&str],
+    alias: Option<&str>,
+    first_param: usize,
+    keys: usize,
+) -> String {
+    let tuple = |values: Vec<String>| {
+        if values.len() == 1 {
+            values.join(", ")
+        } else {
+            format!("({})", values.join(", "))
+        }
+    };
+    format!(
+        "{columns} IN ({values})",
+        columns = tuple(columns.iter().map(|c| aliased(c, alias)).collect()),
+        values = (0..keys)
+            .map(|k| tuple(
+                (0..columns.len())
+                    .map(|i| format!("${}", first_param + k * columns.len() + i))
+                    .collect()
+            ))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_columns() {
+        assert_eq!(
+            <(i64, String) as DbKey>::COLUMNS,
+            2,
+            "tuple keys span one column per element"
+        );
+        assert_eq!(split_columns("id_a, id_b"), vec!["id_a", "id_b"]);
+        assert_eq!(key_columns(&["id_a", "id_b"], Some("a")), "a.id_a, a.id_b");
+    }
+
+    #[test]
+    fn test_time_ordered_keys() {
+        let keys: Vec<Uuid> = (0..10_000).map(|_| uuid_v7()).collect();
+        assert_eq!(keys[0].as_bytes()[6] >> 4, 7);
+        assert_eq!(keys[0].as_bytes()[8] >> 6, 0b10);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let first = uuid_ulid();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = uuid_ulid();
+        assert!(first.as_bytes()[..6] < second.as_bytes()[..6]);
+
+        assert!(Uuid::generate(IdStrategy::Database).is_none());
+        assert!(i64::generate(IdStrategy::Random).is_none());
+    }
+
+    #[test]
+    fn test_key_conditions() {
+        assert_eq!(key_condition(&["id"], None, 1), "id = $1");
+        assert_eq!(
+            key_condition(&["id_a", "id_b"], Some("a"), 3),
+            "a.id_a = $3 AND a.id_b = $4"
+        );
+        assert_eq!(
+            key_assignments(&["id_a", "id_b"], 2),
+            "id_a = $2, id_b = $3"
+        );
+        assert_eq!(
+            key_join(&["id"], Some("b"), &["id_user"], Some("ab")),
+            "b.id = ab.id_user"
+        );
+        assert_eq!(key_in(&["id"], None, 2, 2), "id IN ($2, $3)");
+        assert_eq!(
+            key_in(&["id_a", "id_b"], Some("a"), 1, 2),
+            "(a.id_a, a.id_b) IN (($1, $2), ($3, $4))"
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{quote_literal, DbError, Json, ToSql};
+
+use serde::Serialize;
+use serde_json::Value;
+
+enum FilterParam<'a> {
+    Borrowed(&'a (dyn ToSql + Sync)),
+    Owned(Box<dyn ToSql + Sync + Send>),
+}
+
+/// Builder for query filters over a document column.
+///
+/// Conditions are joined with AND and their positional parameters are renumbered,
+/// so each condition can be written starting from `$1`.
+/// The resulting clause and parameters can be passed to `find_all`, `find_by`
+/// and `DbJoin::fetch_filtered` (whose target table is aliased "a", so the filter
+/// must be created with `Filter::new("a.data")`).
+/// ## Example
+/// ```ignore
+/// let filter = DbEntity::<User>::filter()
+///     .contains(&json!({"address": {"city": "Rome"}}))?
+///     .has_key("email")
+///     .path_exists_with("$.phones[*] ? (@.type == $type)", &json!({"type": "mobile"}))?
+///     .and("data->>'last_name' LIKE $1", &[&"R%"]);
+/// let params = filter.params();
+/// DbEntity::<User>::find_all(db_conn, Some((&filter.clause(), &params)), None, 0, 100);
+/// ```
+pub struct Filter<'a> {
+    data_column: String,
+    conditions: Vec<String>,
+    params: Vec<FilterParam<'a>>,
+}
+
+impl<'a> Filter<'a> {
+    /// Creates an empty filter over the document column *data_column*
+    /// (such as "data", or "a.data" for DbJoin queries)
+    pub fn new(data_column: &str) -> Self {
+        Self {
+            data_column: data_column.to_owned(),
+            conditions: vec![],
+            params: vec![],
+        }
+    }
+
+    fn next_param(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    fn push_owned<P>(mut self, condition: String, param: P) -> Self
+    where
+        P: ToSql + Sync + Send + 'static,
+    {
+        self.conditions.push(condition);
+        self.params.push(FilterParam::Owned(Box::new(param)));
+        self
+    }
+
+    /// Adds a raw condition whose parameters are numbered from `$1`
+    pub fn and(mut self, condition: &str, params: &[&'a (dyn ToSql + Sync)]) -> Self {
+        self.conditions
+            .push(format!("({})", shift_params(condition, self.params.len())));
+        self.params
+            .extend(params.iter().map(|&param| FilterParam::Borrowed(param)));
+        self
+    }
+
+    /// Adds a raw condition whose only parameter (`$1`) is *value* bound as json/jsonb,
+    /// such as `data->'tags' @> $1`.
+    ///
+    /// Borrowed values can also be given to [and](#method.and) wrapped in [Json](struct.Json.html).
+    pub fn and_json<P>(mut self, condition: &str, value: &P) -> Result<Self, DbError>
+    where
+        P: Serialize,
+    {
+        let value = serde_json::to_value(value)?;
+        self.conditions
+            .push(format!("({})", shift_params(condition, self.params.len())));
+        self.params.push(FilterParam::Owned(Box::new(Json(value))));
+        Ok(self)
+    }
+
+    /// Adds a raw condition with named parameters (such as `data->>'name' = :name`),
+    /// whose values are given as (name, value) pairs
+    pub fn and_named(
+        mut self,
+        condition: &str,
+        params: &[(&str, &'a (dyn ToSql + Sync))],
+    ) -> Result<Self, DbError> {
+        let (condition, params) = named_params(condition, params)?;
+        self.conditions
+            .push(format!("({})", shift_params(&condition, self.params.len())));
+        self.params
+            .extend(params.into_iter().map(FilterParam::Borrowed));
+        Ok(self)
+    }
+
+    /// Adds a raw condition with named parameters (such as `data->>'name' = :name`),
+    /// whose values are the fields of *values* (a struct or a map).
+    ///
+    /// Parameters are cast according to the field values: text, boolean, bigint (integers)
+    /// or numeric (other numbers); arrays and objects are bound as jsonb and null values are
+    /// written as NULL.
+    pub fn and_named_from<P>(mut self, condition: &str, values: &P) -> Result<Self, DbError>
+    where
+        P: Serialize,
+    {
+        let values = match serde_json::to_value(values)? {
+            Value::Object(values) => values,
+            _ => {
+                return Err(DbError::new(
+                    "Named parameters must be a struct or a map",
+                    None,
+                ))
+            }
+        };
+        let names: Vec<&str> = values.keys().map(String::as_str).collect();
+        let mut order = vec![];
+        let condition = replace_named(condition, &names, |idx| {
+            let cast = match &values[names[idx]] {
+                Value::Null => return String::from("NULL"),
+                Value::String(_) => "text",
+                Value::Bool(_) => "boolean",
+                Value::Number(value) if value.is_i64() => "bigint",
+                Value::Number(_) => "text::numeric",
+                Value::Array(_) | Value::Object(_) => "jsonb",
+            };
+            format!("${}::{}", positional(&mut order, idx), cast)
+        })?;
+        self.conditions
+            .push(format!("({})", shift_params(&condition, self.params.len())));
+        for idx in order {
+            self.params
+                .push(FilterParam::Owned(match &values[names[idx]] {
+                    Value::String(value) => Box::new(value.clone()),
+                    Value::Bool(value) => Box::new(*value),
+                    Value::Number(value) => match value.as_i64() {
+                        Some(value) => Box::new(value),
+                        None => Box::new(value.to_string()),
+                    },
+                    value => Box::new(Json(value.clone())),
+                }));
+        }
+        Ok(self)
+    }
+
+    /// Matches documents containing *partial* (`@>` operator)
+    pub fn contains<P>(self, partial: &P) -> Result<Self, DbError>
+    where
+        P: Serialize,
+    {
+        let value = serde_json::to_value(partial)?;
+        let condition = format!("{} @> ${}", self.data_column, self.next_param());
+        Ok(self.push_owned(condition, value))
+    }
+
+    /// Matches documents having the top level *key* (`?` operator)
+    pub fn has_key(self, key: &str) -> Self {
+        let condition = format!("{} ? ${}", self.data_column, self.next_param());
+        self.push_owned(condition, key.to_owned())
+    }
+
+    /// Matches documents having at least one of the top level *keys* (`?|` operator)
+    pub fn has_any_key(self, keys: &[&str]) -> Self {
+        let condition = format!("{} ?| ${}", self.data_column, self.next_param());
+        self.push_owned(condition, to_strings(keys))
+    }
+
+    /// Matches documents having all the top level *keys* (`?&` operator)
+    pub fn has_all_keys(self, keys: &[&str]) -> Self {
+        let condition = format!("{} ?& ${}", self.data_column, self.next_param());
+        self.push_owned(condition, to_strings(keys))
+    }
+
+    /// Matches documents for which the SQL/JSONPath *path* returns any item (`@?` operator).
+    ///
+    /// The path is written in the clause as a quoted literal rather than bound, so the
+    /// condition can use a GIN index on the document column.
+    pub fn path_exists(mut self, path: &str) -> Self {
+        self.conditions.push(format!(
+            "{} @? {}::jsonpath",
+            self.data_column,
+            quote_literal(path)
+        ));
+        self
+    }
+
+    /// Matches documents for which the SQL/JSONPath predicate *path* is true (`@@` operator).
+    /// As in [path_exists](#method.path_exists), the path is written as a quoted literal.
+    pub fn path_match(mut self, path: &str) -> Self {
+        self.conditions.push(format!(
+            "{} @@ {}::jsonpath",
+            self.data_column,
+            quote_literal(path)
+        ));
+        self
+    }
+
+    /// As path_exists, where *vars* is serialized as the JSON object holding the
+    /// variables referenced by the path (such as `$min`).
+    ///
+    /// The condition calls jsonb_path_exists, which cannot use an index: prefer path_exists
+    /// with the values written in the path when filtering large tables.
+    pub fn path_exists_with<V>(self, path: &str, vars: &V) -> Result<Self, DbError>
+    where
+        V: Serialize,
+    {
+        self.path_function("jsonb_path_exists", path, vars)
+    }
+
+    /// As path_match, where *vars* is serialized as the JSON object holding the
+    /// variables referenced by the path (such as `$min`).
+    ///
+    /// The condition calls jsonb_path_match, which cannot use an index (see
+    /// [path_exists_with](#method.path_exists_with)).
+    pub fn path_match_with<V>(self, path: &str, vars: &V) -> Result<Self, DbError>
+    where
+        V: Serialize,
+    {
+        self.path_function("jsonb_path_match", path, vars)
+    }
+
+    fn path_function<V>(self, function: &str, path: &str, vars: &V) -> Result<Self, DbError>
+    where
+        V: Serialize,
+    {
+        let vars = serde_json::to_value(vars)?;
+        let condition = format!(
+            "{function}({data_column}, ${path}::text::jsonpath, ${vars}::jsonb)",
+            function = function,
+            data_column = self.data_column,
+            path = self.next_param(),
+            vars = self.next_param() + 1,
+        );
+        let mut filter = self.push_owned(condition, path.to_owned());
+        filter.params.push(FilterParam::Owned(Box::new(vars)));
+        Ok(filter)
+    }
+
+    /// The filter clause. An empty filter matches every record.
+    pub fn clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::from("TRUE")
+        } else {
+            self.conditions.join(" AND ")
+        }
+    }
+
+    /// The filter parameters
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|param| match param {
+                FilterParam::Borrowed(param) => *param,
+                FilterParam::Owned(param) => param.as_ref() as &(dyn ToSql + Sync),
+            })
+            .collect()
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|&value| value.to_owned()).collect()
+}
+
+/// Translates the named parameters (`:name`) of *clause* into positional parameters (`$n`),
+/// returning the translated clause and its parameter values. Repeated names are bound once.
+///
+/// Casts (`::type`) and quoted literals or identifiers are left untouched.
+/// ## Example
+/// ```rust
+/// use orma::named_params;
+///
+/// let (clause, params) = named_params(
+///     "data->>'name' = :name OR data->>'alias' = :name AND (data->>'age')::int > :age",
+///     &[("age", &18), ("name", &"orma")],
+/// )
+/// .unwrap();
+/// assert_eq!(clause, "data->>'name' = $1 OR data->>'alias' = $1 AND (data->>'age')::int > $2");
+/// assert_eq!(params.len(), 2);
+/// ```
+pub fn named_params<'a>(
+    clause: &str,
+    params: &[(&str, &'a (dyn ToSql + Sync))],
+) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>), DbError> {
+    let names: Vec<&str> = params.iter().map(|(name, _)| *name).collect();
+    let (clause, order) = translate_named(clause, &names)?;
+    Ok((clause, order.into_iter().map(|idx| params[idx].1).collect()))
+}
+
+/// Translates the named parameters of *clause*, returning the indexes in *names*
+/// of the values to bind for each positional parameter
+fn translate_named(clause: &str, names: &[&str]) -> Result<(String, Vec<usize>), DbError> {
+    let mut order = vec![];
+    let clause = replace_named(clause, names, |idx| {
+        format!("${}", positional(&mut order, idx))
+    })?;
+    Ok((clause, order))
+}
+
+/// The positional parameter bound to the value at *idx*, added to *order* when new
+fn positional(order: &mut Vec<usize>, idx: usize) -> usize {
+    match order.iter().position(|&i| i == idx) {
+        Some(param) => param + 1,
+        None => {
+            order.push(idx);
+            order.len()
+        }
+    }
+}
+
+/// Replaces the named parameters of *clause* with *placeholder(idx)*, where idx is the
+/// index of the parameter name in *names*
+fn replace_named<F>(clause: &str, names: &[&str], mut placeholder: F) -> Result<String, DbError>
+where
+    F: FnMut(usize) -> String,
+{
+    let mut result = String::with_capacity(clause.len());
+    let mut quote: Option<char> = None;
+    let mut chars = clause.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, ':') if chars.peek() == Some(&':') => {
+                result.push(c);
+                chars.next();
+            }
+            (None, ':')
+                if chars
+                    .peek()
+                    .is_some_and(|&c| c.is_ascii_alphabetic() || c == '_') =>
+            {
+                let mut name = String::new();
+                while let Some(c) = chars
+                    .peek()
+                    .copied()
+                    .filter(|&c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    name.push(c);
+                    chars.next();
+                }
+                let idx = names.iter().position(|&n| n == name).ok_or_else(|| {
+                    DbError::new(&format!("Missing value for parameter :{}", name), None)
+                })?;
+                result.push_str(&placeholder(idx));
+                continue;
+            }
+            _ => (),
+        }
+        result.push(c);
+    }
+    Ok(result)
+}
+
+/// Adds *offset* to every positional parameter (`$n`) of *clause* that is not
+/// inside a quoted literal or identifier
+pub(crate) fn shift_params(clause: &str, offset: usize) -> String {
+    if offset == 0 {
+        return clause.to_owned();
+    }
+    let mut result = String::with_capacity(clause.len());
+    let mut quote: Option<char> = None;
+    let mut chars = clause.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '$') if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let mut number = String::new();
+                while let Some(digit) = chars.peek().copied().filter(char::is_ascii_digit) {
+                    number.push(digit);
+                    chars.next();
+                }
+                let number: usize = number.parse().unwrap_or_default();
+                result.push_str(&format!("${}", number + offset));
+                continue;
+            }
+            _ => (),
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_shift_params() {
+        assert_eq!(shift_params("data->>'a' = $1", 0), "data->>'a' = $1");
+        assert_eq!(
+            shift_params("data->>'a' = $1 AND data->>'b' IN ($2, $10)", 2),
+            "data->>'a' = $3 AND data->>'b' IN ($4, $12)"
+        );
+        assert_eq!(
+            shift_params("data->>'$1' = $1 AND \"$2\" = $2", 1),
+            "data->>'$1' = $2 AND \"$2\" = $3"
+        );
+    }
+
+    #[test]
+    fn test_named_params() {
+        let (clause, order) = translate_named(
+            "data->>'a' = :b AND x::text = ':a' AND y = :a OR z = :b",
+            &["a", "b"],
+        )
+        .unwrap();
+        assert_eq!(
+            clause,
+            "data->>'a' = $1 AND x::text = ':a' AND y = $2 OR z = $1"
+        );
+        assert_eq!(order, vec![1, 0]);
+        assert!(translate_named("data->>'a' = :c", &["a"]).is_err());
+
+        let filter = Filter::new("data")
+            .has_key("name")
+            .and_named_from(
+                "data->>'name' = :name AND (data->>'age')::bigint > :age AND \
+                 (:tag IS NULL OR data->>'tag' = :tag) AND (data->>'size')::numeric < :size",
+                &json!({"name": "orma", "age": 3, "tag": null, "size": 1.5, "unused": true}),
+            )
+            .unwrap();
+        assert_eq!(
+            filter.clause(),
+            "data ? $1 AND (data->>'name' = $2::text AND (data->>'age')::bigint > $3::bigint AND \
+             (NULL IS NULL OR data->>'tag' = NULL) AND (data->>'size')::numeric < $4::text::numeric)"
+        );
+        assert_eq!(filter.params().len(), 4);
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::new("data")
+            .contains(&json!({"name": "orma"}))
+            .unwrap()
+            .and("data->>'a' = $1 OR data->>'b' = $2", &[&"a", &"b"])
+            .has_key("email")
+            .has_any_key(&["x", "y"])
+            .has_all_keys(&["x", "y"]);
+        assert_eq!(
+            filter.clause(),
+            "data @> $1 AND (data->>'a' = $2 OR data->>'b' = $3) AND data ? $4 \
+             AND data ?| $5 AND data ?& $6"
+        );
+        assert_eq!(filter.params().len(), 6);
+        assert_eq!(Filter::new("a.data").clause(), "TRUE");
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = Filter::new("a.data")
+            .path_exists("$.tags[*] ? (@ == \"rust\")")
+            .path_match_with("$.price > $min", &json!({"min": 10}))
+            .unwrap()
+            .path_match("$.stock > 0");
+        assert_eq!(
+            filter.clause(),
+            "a.data @? '$.tags[*] ? (@ == \"rust\")'::jsonpath \
+             AND jsonb_path_match(a.data, $1::text::jsonpath, $2::jsonb) \
+             AND a.data @@ '$.stock > 0'::jsonpath"
+        );
+        assert_eq!(filter.params().len(), 2);
+    }
+}
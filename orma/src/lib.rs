@@ -33,6 +33,8 @@
 //! - _version_ is used for record versioning and prevents a record to be modified if version has changed in another session. orma takes care of record versioning OOTB.
 //! - _data_ is used to map the document and it's mapped over structs that implement _DbData_ trait.
 //!
+//! Column names can be changed for each _DbData_ with the *ID_COLUMNS*, *VERSION_COLUMN* and *DATA_COLUMN* constants.
+//!
 //! All structs that implement *DbData* trait need to be serializable too, as they'll be represented as jsonb data in your db records.
 //!
 //! While _DbData_ is mapped to _data_ column, _DbEntity_ is mapped over the three columns, and table, just described.
@@ -77,14 +79,36 @@
 //! }
 //! ```
 
+mod aggregate;
+mod atomic;
 mod db_anti_corruption;
 mod dbentity;
 mod dbjoin;
+mod dbkey;
+mod filter;
+mod patch;
+mod search;
+mod session;
+mod similarity;
+mod sort;
+mod sql;
 
+pub use aggregate::Aggregate;
 pub use db_anti_corruption::tls;
 pub use db_anti_corruption::*;
-pub use dbentity::{select_part, DbData, DbEntity};
+pub use dbentity::{
+    promote_fields, promoted_fields_ddl, select_part, select_part_for, DbData, DbEntity,
+    PromotedField,
+};
 pub use dbjoin::{DbJoin, JoinBuilder};
+pub use dbkey::{uuid_ulid, uuid_v7, DbKey, IdStrategy};
+pub use filter::{named_params, Filter};
+pub use patch::PatchOperation;
+pub use search::{search_index_ddl, search_vector_expr, SearchField, SearchOptions, SearchResult};
+pub use session::{Session, SharedEntity};
+pub use similarity::{trigram_index_ddl, SimilarityMode, SimilarityOptions, SimilarityResult};
+pub use sort::{Nulls, Sort};
+pub use sql::{check_identifier, json_path_expr, quote_ident, quote_literal, Cast};
 pub use uuid::Uuid;
 
 #[macro_export]
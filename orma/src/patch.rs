@@ -0,0 +1,527 @@
+use crate::{
+    dbentity, dbkey, quote_literal, Connection, DbData, DbEntity, DbError, DbKey, Json, ToSql,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A JSON Patch (RFC 6902) operation, where paths are JSON Pointers (such as "/tags/0").
+///
+/// It serializes to (and deserializes from) the RFC format, such as
+/// `{"op": "replace", "path": "/name", "value": "John"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add {
+        path: String,
+        value: Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    /// Checks that the value at *path* equals *value*
+    Test {
+        path: String,
+        value: Value,
+    },
+}
+
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn pointer_segments(pointer: &str) -> Result<Vec<String>, DbError> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    match pointer.strip_prefix('/') {
+        Some(tokens) => Ok(tokens
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect()),
+        None => Err(DbError::new(
+            &format!("Invalid JSON Pointer {:?}", pointer),
+            None,
+        )),
+    }
+}
+
+fn diff_into(path: &str, from: &Value, to: &Value, operations: &mut Vec<PatchOperation>) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (Value::Object(from_members), Value::Object(to_members)) => {
+            for key in from_members.keys() {
+                if !to_members.contains_key(key) {
+                    operations.push(PatchOperation::Remove {
+                        path: format!("{}/{}", path, escape_token(key)),
+                    });
+                }
+            }
+            for (key, value) in to_members {
+                let member_path = format!("{}/{}", path, escape_token(key));
+                match from_members.get(key) {
+                    Some(from_value) => diff_into(&member_path, from_value, value, operations),
+                    None => operations.push(PatchOperation::Add {
+                        path: member_path,
+                        value: value.clone(),
+                    }),
+                }
+            }
+        }
+        _ => operations.push(PatchOperation::Replace {
+            path: path.to_owned(),
+            value: to.clone(),
+        }),
+    }
+}
+
+/// The JSON Patch turning *from* into *to*. Objects are compared member by member,
+/// other values (arrays included) are replaced as a whole.
+pub(crate) fn diff(from: &Value, to: &Value) -> Vec<PatchOperation> {
+    let mut operations = vec![];
+    diff_into("", from, to, &mut operations);
+    operations
+}
+
+fn path_array(segments: &[String]) -> String {
+    format!(
+        "ARRAY[{}]::text[]",
+        segments
+            .iter()
+            .map(|segment| quote_literal(segment))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+fn exists_expr(doc: &str, segments: &[String]) -> String {
+    format!("{} #> {} IS NOT NULL", doc, path_array(segments))
+}
+
+/// Adds *value* at *segments* of *doc*: members of objects are set, items are inserted
+/// into arrays ("-" appends).
+fn add_expr(doc: &str, segments: &[String], value: &str) -> String {
+    let (last, parent) = match segments.split_last() {
+        Some(split) => split,
+        None => return format!("CASE WHEN {} IS NOT NULL THEN {} END", doc, value),
+    };
+    let parent_expr = format!("{} #> {}", doc, path_array(parent));
+    let array_expr = if last == "-" {
+        if parent.is_empty() {
+            format!("{} || jsonb_build_array({})", doc, value)
+        } else {
+            format!(
+                "jsonb_set({doc}, {parent}, ({parent_expr}) || jsonb_build_array({value}))",
+                doc = doc,
+                parent = path_array(parent),
+                parent_expr = parent_expr,
+                value = value,
+            )
+        }
+    } else {
+        match last.parse::<u32>() {
+            Ok(index) => format!(
+                "CASE WHEN {index} <= jsonb_array_length({parent_expr}) \
+                 THEN jsonb_insert({doc}, {path}, {value}) END",
+                index = index,
+                parent_expr = parent_expr,
+                doc = doc,
+                path = path_array(segments),
+                value = value,
+            ),
+            Err(_) => String::from("NULL"),
+        }
+    };
+    format!(
+        "CASE jsonb_typeof({parent_expr}) \
+         WHEN 'object' THEN jsonb_set({doc}, {path}, {value}) \
+         WHEN 'array' THEN {array_expr} END",
+        parent_expr = parent_expr,
+        doc = doc,
+        path = path_array(segments),
+        value = value,
+        array_expr = array_expr,
+    )
+}
+
+fn operation_expr(
+    doc: &str,
+    operation: &PatchOperation,
+    values: &mut Vec<Json<Value>>,
+    first_param: usize,
+) -> Result<String, DbError> {
+    let mut bind = |value: &Value| {
+        values.push(Json(value.clone()));
+        format!("${}::jsonb", first_param + values.len() - 1)
+    };
+    Ok(match operation {
+        PatchOperation::Add { path, value } => {
+            add_expr(doc, &pointer_segments(path)?, &bind(value))
+        }
+        PatchOperation::Remove { path } => {
+            let segments = pointer_segments(path)?;
+            if segments.is_empty() {
+                return Err(DbError::new("The whole document cannot be removed", None));
+            }
+            format!(
+                "CASE WHEN {exists} THEN {doc} #- {path} END",
+                exists = exists_expr(doc, &segments),
+                doc = doc,
+                path = path_array(&segments),
+            )
+        }
+        PatchOperation::Replace { path, value } => {
+            let segments = pointer_segments(path)?;
+            let value = bind(value);
+            if segments.is_empty() {
+                format!("CASE WHEN {} IS NOT NULL THEN {} END", doc, value)
+            } else {
+                format!(
+                    "CASE WHEN {exists} THEN jsonb_set({doc}, {path}, {value}, false) END",
+                    exists = exists_expr(doc, &segments),
+                    doc = doc,
+                    path = path_array(&segments),
+                    value = value,
+                )
+            }
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(DbError::new(
+                    &format!("{:?} cannot be moved into {:?}", from, path),
+                    None,
+                ));
+            }
+            let from_segments = pointer_segments(from)?;
+            if from_segments.is_empty() {
+                return Err(DbError::new("The whole document cannot be moved", None));
+            }
+            format!(
+                "CASE WHEN {exists} THEN {add} END",
+                exists = exists_expr(doc, &from_segments),
+                add = add_expr(
+                    &format!("({} #- {})", doc, path_array(&from_segments)),
+                    &pointer_segments(path)?,
+                    &format!("{} #> {}", doc, path_array(&from_segments)),
+                ),
+            )
+        }
+        PatchOperation::Copy { from, path } => {
+            let from_segments = pointer_segments(from)?;
+            format!(
+                "CASE WHEN {exists} THEN {add} END",
+                exists = exists_expr(doc, &from_segments),
+                add = add_expr(
+                    doc,
+                    &pointer_segments(path)?,
+                    &format!("{} #> {}", doc, path_array(&from_segments)),
+                ),
+            )
+        }
+        PatchOperation::Test { path, value } => format!(
+            "CASE WHEN {doc} #> {path} = {value} THEN {doc} END",
+            doc = doc,
+            path = path_array(&pointer_segments(path)?),
+            value = bind(value),
+        ),
+    })
+}
+
+/// SQL expression applying the JSON Patch *operations* to the *target* JSONB expression, one
+/// after the other. The expression is NULL when an operation cannot be applied (failed test,
+/// missing path). Values are pushed to *values* and bound as jsonb parameters, numbered from
+/// *first_param*.
+fn json_patch_expr(
+    target: &str,
+    operations: &[PatchOperation],
+    values: &mut Vec<Json<Value>>,
+    first_param: usize,
+) -> Result<String, DbError> {
+    operations
+        .iter()
+        .enumerate()
+        .try_fold(target.to_owned(), |expr, (i, operation)| {
+            let alias = format!("patch_{}", i);
+            Ok(format!(
+                "(SELECT {operation} FROM (SELECT {expr} AS doc) {alias})",
+                operation =
+                    operation_expr(&format!("{}.doc", alias), operation, values, first_param)?,
+                expr = expr,
+                alias = alias,
+            ))
+        })
+}
+
+/// SQL expression applying the JSON Merge Patch (RFC 7396) *patch* to the *target* JSONB
+/// expression. Values of the patch that are not merged objects are pushed to *values*
+/// and bound as jsonb parameters, numbered from *first_param*.
+fn merge_patch_expr(
+    target: &str,
+    patch: &Value,
+    depth: usize,
+    values: &mut Vec<Json<Value>>,
+    first_param: usize,
+) -> String {
+    match patch {
+        Value::Object(members) => {
+            let alias = format!("merge_{}", depth);
+            let removed = members
+                .keys()
+                .map(|key| quote_literal(key))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let merged = members
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| {
+                    let member_target = format!("{}.doc -> {}", alias, quote_literal(key));
+                    format!(
+                        "{}, {}",
+                        quote_literal(key),
+                        merge_patch_expr(&member_target, value, depth + 1, values, first_param)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+            format!(
+                "(SELECT ({alias}.doc - ARRAY[{removed}]::text[]) || jsonb_build_object({merged}) \
+                 FROM (SELECT CASE WHEN jsonb_typeof({target}) = 'object' THEN {target} \
+                 ELSE '{{}}'::jsonb END AS doc) {alias})",
+                alias = alias,
+                removed = removed,
+                merged = merged,
+                target = target,
+            )
+        }
+        value => {
+            values.push(Json(value.clone()));
+            format!("${}::jsonb", first_param + values.len() - 1)
+        }
+    }
+}
+
+impl<T> DbEntity<T>
+where
+    T: DbData,
+{
+    /// Applies a JSON Merge Patch (RFC 7396) to the stored document: members of *patch* replace
+    /// the ones of the document, nested objects are merged and null members are removed.
+    ///
+    /// The merge is computed by the database on the stored record, which must still have the
+    /// version of the entity; the merged document must deserialize into *T* before it is
+    /// written. The entity is then updated with the new data and version.
+    /// ## Example
+    /// ```ignore
+    /// user.merge_patch(db_conn, json!({"email": "new@example.com", "phone": null}))
+    ///     .await?;
+    /// ```
+    pub async fn merge_patch(&mut self, conn: &Connection, patch: Value) -> Result<(), DbError> {
+        dbentity::check_names::<T>()?;
+        let mut values = vec![];
+        let merged_expr =
+            merge_patch_expr(T::DATA_COLUMN, &patch, 0, &mut values, T::Id::COLUMNS + 2);
+        let prepared_s = conn
+            .prepare(&format!(
+                "SELECT {merged_expr} FROM {table_name} WHERE {id_condition} AND {version_column} = ${version}",
+                merged_expr = merged_expr,
+                table_name = T::table_name(),
+                id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+                version_column = T::VERSION_COLUMN,
+                version = T::Id::COLUMNS + 1,
+            ))
+            .await?;
+        let mut params = self.id.params();
+        params.push(&self.version);
+        params.extend(values.iter().map(|value| value as &(dyn ToSql + Sync)));
+        let merged: Json<Value> = match conn.query_opt(&prepared_s, &params).await? {
+            Some(row) => row.get(0),
+            None => return Err(self.out_of_sync_err()),
+        };
+        serde_json::from_value::<T>(merged.0.clone())?;
+
+        let data_expr = format!("${}::jsonb", T::Id::COLUMNS + 1);
+        let condition = format!("{} = ${}", T::VERSION_COLUMN, T::Id::COLUMNS + 2);
+        let version = self.version;
+        match self
+            .update_data_with(
+                conn,
+                &data_expr,
+                Some(&condition),
+                None,
+                &[&merged, &version],
+            )
+            .await?
+        {
+            Some(_) => Ok(()),
+            None => Err(self.out_of_sync_err()),
+        }
+    }
+
+    /// Applies the JSON Patch (RFC 6902) *operations* to the stored document. "test" operations
+    /// are checked by the database against the stored document; when any operation cannot be
+    /// applied nothing is written.
+    ///
+    /// The patched document is validated as *T* before it is written, and it is only written
+    /// when the record did not change in the meantime.
+    /// As with array operations, the version of the entity is not checked: the version is
+    /// incremented and the entity is updated with the stored record.
+    /// ## Example
+    /// ```ignore
+    /// let ops = serde_json::from_value(json!([
+    ///     {"op": "test", "path": "/status", "value": "draft"},
+    ///     {"op": "replace", "path": "/status", "value": "published"},
+    /// ]))?;
+    /// article.apply_json_patch(db_conn, &ops).await?;
+    /// ```
+    pub async fn apply_json_patch(
+        &mut self,
+        conn: &Connection,
+        operations: &[PatchOperation],
+    ) -> Result<(), DbError> {
+        dbentity::check_names::<T>()?;
+        let mut values = vec![];
+        let patched_expr =
+            json_patch_expr(T::DATA_COLUMN, operations, &mut values, T::Id::COLUMNS + 1)?;
+        let prepared_s = conn
+            .prepare(&format!(
+                "SELECT {patched_expr}, {version_column} FROM {table_name} WHERE {id_condition}",
+                patched_expr = patched_expr,
+                version_column = T::VERSION_COLUMN,
+                table_name = T::table_name(),
+                id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1),
+            ))
+            .await?;
+        let mut params = self.id.params();
+        params.extend(values.iter().map(|value| value as &(dyn ToSql + Sync)));
+        let (patched, version): (Json<Value>, i32) =
+            match conn.query_opt(&prepared_s, &params).await? {
+                Some(row) => match row.get::<_, Option<Json<Value>>>(0) {
+                    Some(patched) => (patched, row.get(1)),
+                    None => {
+                        return Err(DbError::new(
+                            &format!("{:?}: patch not applicable", self.id),
+                            None,
+                        ))
+                    }
+                },
+                None => return Err(DbError::new(&format!("{:?} not found", self.id), None)),
+            };
+        serde_json::from_value::<T>(patched.0.clone())?;
+
+        let data_expr = format!("${}::jsonb", T::Id::COLUMNS + 1);
+        let condition = format!("{} = ${}", T::VERSION_COLUMN, T::Id::COLUMNS + 2);
+        match self
+            .update_data_with(
+                conn,
+                &data_expr,
+                Some(&condition),
+                None,
+                &[&patched, &version],
+            )
+            .await?
+        {
+            Some(_) => Ok(()),
+            None => Err(DbError::new(
+                &format!("{:?} changed while patching", self.id),
+                None,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch_expr() {
+        let mut values = vec![];
+        let expr = merge_patch_expr(
+            "data",
+            &json!({"a": 1, "b": null, "c": {"d": [2]}}),
+            0,
+            &mut values,
+            3,
+        );
+        assert_eq!(
+            expr,
+            "(SELECT (merge_0.doc - ARRAY['a', 'b', 'c']::text[]) || jsonb_build_object('a', $3::jsonb, 'c', \
+             (SELECT (merge_1.doc - ARRAY['d']::text[]) || jsonb_build_object('d', $4::jsonb) \
+             FROM (SELECT CASE WHEN jsonb_typeof(merge_0.doc -> 'c') = 'object' THEN merge_0.doc -> 'c' \
+             ELSE '{}'::jsonb END AS doc) merge_1)) \
+             FROM (SELECT CASE WHEN jsonb_typeof(data) = 'object' THEN data ELSE '{}'::jsonb END AS doc) merge_0)"
+        );
+        assert_eq!(
+            values
+                .into_iter()
+                .map(Json::into_inner)
+                .collect::<Vec<Value>>(),
+            vec![json!(1), json!([2])]
+        );
+
+        let mut values = vec![];
+        assert_eq!(
+            merge_patch_expr("data", &json!([1]), 0, &mut values, 1),
+            "$1::jsonb"
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let from = json!({"a": 1, "b": {"c": [1], "d": "x"}, "e/f": true});
+        let to = json!({"a": 1, "b": {"c": [1, 2], "g": null}, "h": 2});
+        assert_eq!(
+            diff(&from, &to),
+            vec![
+                PatchOperation::Remove {
+                    path: String::from("/e~1f")
+                },
+                PatchOperation::Remove {
+                    path: String::from("/b/d")
+                },
+                PatchOperation::Replace {
+                    path: String::from("/b/c"),
+                    value: json!([1, 2])
+                },
+                PatchOperation::Add {
+                    path: String::from("/b/g"),
+                    value: json!(null)
+                },
+                PatchOperation::Add {
+                    path: String::from("/h"),
+                    value: json!(2)
+                },
+            ]
+        );
+        assert!(diff(&from, &from).is_empty());
+        assert_eq!(
+            serde_json::to_value(diff(&json!(1), &json!(2))).unwrap(),
+            json!([{"op": "replace", "path": "", "value": 2}])
+        );
+    }
+
+    #[test]
+    fn test_pointer_segments() {
+        assert_eq!(pointer_segments("").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            pointer_segments("/a~1b/0/~0").unwrap(),
+            vec!["a/b", "0", "~"]
+        );
+        assert!(pointer_segments("a").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    dbentity, json_path_expr, quote_literal, Cast, Connection, DbData, DbEntity, DbError, DbKey,
+    ToSql,
+};
+
+/// A document field indexed for full-text search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchField {
+    /// Dot separated path of the field inside the data document (such as "address.city")
+    pub path: &'static str,
+    /// Weight of the field in ranking, from 'A' (highest) to 'D' (lowest)
+    pub weight: char,
+}
+
+/// Options of [DbEntity::search](struct.DbEntity.html#method.search)
+pub struct SearchOptions<'a> {
+    /// Optional filter tuple, as in find_all
+    pub filter: Option<(&'a str, &'a [&'a (dyn ToSql + Sync)])>,
+    /// When true each result carries a snippet of the searchable text with matches highlighted
+    pub snippets: bool,
+    /// ts_headline options for snippets (such as "StartSel=<b>, StopSel=</b>, MaxWords=20")
+    pub headline_options: Option<&'a str>,
+    pub offset: i64,
+    /// Maximum number of results. A negative limit returns all of them.
+    pub limit: i64,
+}
+
+impl<'a> Default for SearchOptions<'a> {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            snippets: false,
+            headline_options: None,
+            offset: 0,
+            limit: -1,
+        }
+    }
+}
+
+/// A full-text search match with its rank
+pub struct SearchResult<T>
+where
+    T: DbData,
+{
+    pub entity: DbEntity<T>,
+    /// ts_rank of the match; results are sorted by descending rank
+    pub rank: f32,
+    /// The highlighted snippet, when requested
+    pub snippet: Option<String>,
+}
+
+fn search_config<T: DbData>() -> String {
+    format!("{}::regconfig", quote_literal(T::SEARCH_CONFIG))
+}
+
+fn search_text(data_column: &str, field: &SearchField) -> String {
+    format!(
+        "coalesce({}, '')",
+        json_path_expr(data_column, field.path, Cast::Text)
+    )
+}
+
+/// The weighted tsvector expression over the search fields of *T*.
+///
+/// Indexes created by [search_index_ddl](fn.search_index_ddl.html) are only used by queries
+/// with this very same expression.
+pub fn search_vector_expr<T: DbData>() -> String {
+    T::search_fields()
+        .iter()
+        .map(|field| {
+            format!(
+                "setweight(to_tsvector({config}, {text}), '{weight}')",
+                config = search_config::<T>(),
+                text = search_text(T::DATA_COLUMN, field),
+                weight = field.weight,
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" || ")
+}
+
+/// Returns the DDL statement creating the GIN expression index used by full-text searches
+/// over the search fields of a DbData (None when it has no search fields).
+///
+/// ## Example
+/// ```ignore
+/// if let Some(statement) = search_index_ddl::<User>() {
+///     conn.batch_execute(&statement).await?;
+/// }
+/// ```
+pub fn search_index_ddl<T: DbData>() -> Option<String> {
+    if T::search_fields().is_empty() {
+        return None;
+    }
+    let table_name = T::table_name();
+    Some(format!(
+        "CREATE INDEX IF NOT EXISTS {short_name}_search_ix ON {table_name} USING gin (({vector}))",
+        short_name = table_name.rsplit('.').next().unwrap_or(table_name),
+        table_name = table_name,
+        vector = search_vector_expr::<T>(),
+    ))
+}
+
+fn search_sql<T: DbData>(options: &SearchOptions) -> String {
+    let filter_len = options.filter.map_or(0, |(_, filter_val)| filter_val.len());
+    let mut next_param = filter_len + 2;
+    let snippet = if options.snippets {
+        let text = format!(
+            "concat_ws(' ', {})",
+            T::search_fields()
+                .iter()
+                .map(|field| search_text(T::DATA_COLUMN, field))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        let headline_options = match options.headline_options {
+            Some(_) => {
+                next_param += 1;
+                format!(", ${}", next_param - 1)
+            }
+            None => String::from(""),
+        };
+        format!(
+            ", ts_headline({config}, {text}, search_query{headline_options})",
+            config = search_config::<T>(),
+            text = text,
+            headline_options = headline_options,
+        )
+    } else {
+        String::from("")
+    };
+    format!(
+        "SELECT {columns}, ts_rank({vector}, search_query) AS search_rank{snippet} \
+         FROM {table_name}, websearch_to_tsquery({config}, ${query}) search_query \
+         WHERE {vector} @@ search_query{filter} \
+         ORDER BY search_rank DESC OFFSET ${offset}{limit}",
+        columns = dbentity::entity_columns::<T>(None),
+        vector = search_vector_expr::<T>(),
+        snippet = snippet,
+        table_name = T::table_name(),
+        config = search_config::<T>(),
+        query = filter_len + 1,
+        filter = match options.filter {
+            Some((filter, _)) => format!(
+                " AND ({})",
+                dbentity::promote_fields(filter, T::DATA_COLUMN, T::promoted_fields())
+            ),
+            None => String::from(""),
+        },
+        offset = next_param,
+        limit = if options.limit < 0 {
+            String::from("")
+        } else {
+            format!(" LIMIT ${}", next_param + 1)
+        },
+    )
+}
+
+impl<T> DbEntity<T>
+where
+    T: DbData,
+{
+    /// Full-text search over the [search fields](trait.DbData.html#method.search_fields)
+    /// of the documents. *query* uses web search syntax (quoted phrases, `or`, `-` to exclude words).
+    ///
+    /// Results are sorted by descending rank.
+    /// ## Example
+    /// ```ignore
+    /// let results = DbEntity::<User>::search(
+    ///     db_conn,
+    ///     "rome -milan",
+    ///     &SearchOptions {
+    ///         snippets: true,
+    ///         limit: 20,
+    ///         ..SearchOptions::default()
+    ///     },
+    /// )
+    /// .await?;
+    /// ```
+    pub async fn search(
+        conn: &Connection,
+        query: &str,
+        options: &SearchOptions<'_>,
+    ) -> Result<Vec<SearchResult<T>>, DbError> {
+        dbentity::check_names::<T>()?;
+        if T::search_fields().is_empty() {
+            return Err(DbError::new(
+                &format!("{} has no search fields", T::table_name()),
+                None,
+            ));
+        }
+        let prepared_s = conn.prepare(&search_sql::<T>(options)).await?;
+        let mut params: Vec<&(dyn ToSql + Sync)> = match options.filter {
+            Some((_, filter_val)) => filter_val.to_vec(),
+            None => vec![],
+        };
+        params.push(&query);
+        if options.snippets {
+            if let Some(headline_options) = &options.headline_options {
+                params.push(headline_options);
+            }
+        }
+        params.push(&options.offset);
+        if options.limit >= 0 {
+            params.push(&options.limit);
+        }
+
+        let rank_idx = T::Id::COLUMNS + 2 + T::EXTRA_COLUMNS.len();
+        conn.query(&prepared_s, &params)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(SearchResult {
+                    entity: DbEntity::from_row(row)?,
+                    rank: row.get(rank_idx),
+                    snippet: if options.snippets {
+                        row.get(rank_idx + 1)
+                    } else {
+                        None
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::*;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize)]
+    struct Article {}
+
+    impl DbData for Article {
+        type Id = Uuid;
+        const SEARCH_CONFIG: &'static str = "english";
+
+        fn table_name() -> &'static str {
+            "intrared.articles"
+        }
+        fn search_fields() -> &'static [SearchField] {
+            &[
+                SearchField {
+                    path: "title",
+                    weight: 'A',
+                },
+                SearchField {
+                    path: "body.text",
+                    weight: 'C',
+                },
+            ]
+        }
+        fn id(&self) -> Option<Uuid> {
+            None
+        }
+        fn version(&self) -> Option<i32> {
+            None
+        }
+        fn set_id(&mut self, _uuid: Uuid) {}
+        fn set_version(&mut self, _version: i32) {}
+    }
+
+    #[test]
+    fn test_search_ddl() {
+        assert_eq!(
+            search_index_ddl::<Article>().unwrap(),
+            "CREATE INDEX IF NOT EXISTS articles_search_ix ON intrared.articles USING gin ((\
+             setweight(to_tsvector('english'::regconfig, coalesce(data->>'title', '')), 'A') || \
+             setweight(to_tsvector('english'::regconfig, coalesce(data->'body'->>'text', '')), 'C')))"
+        );
+    }
+
+    #[test]
+    fn test_search_sql() {
+        let options = SearchOptions {
+            filter: Some(("data->>'lang' = $1", &[&"en"])),
+            snippets: true,
+            headline_options: Some("MaxWords=10"),
+            limit: 10,
+            ..SearchOptions::default()
+        };
+        let sql = search_sql::<Article>(&options);
+        assert!(sql.contains("websearch_to_tsquery('english'::regconfig, $2) search_query"));
+        assert!(sql.contains(
+            "ts_headline('english'::regconfig, concat_ws(' ', coalesce(data->>'title', ''), \
+             coalesce(data->'body'->>'text', '')), search_query, $3)"
+        ));
+        assert!(sql.contains(" AND (data->>'lang' = $1)"));
+        assert!(sql.ends_with("ORDER BY search_rank DESC OFFSET $4 LIMIT $5"));
+    }
+}
@@ -0,0 +1,364 @@
+use crate::dbentity::SyncState;
+use crate::{dbkey, Connection, DbData, DbEntity, DbError, DbJoin, DbKey};
+use futures::future::BoxFuture;
+use futures::lock::Mutex;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// An entity tracked by a [Session](struct.Session.html), shared by all of its lookups
+pub type SharedEntity<T> = Arc<Mutex<DbEntity<T>>>;
+
+trait Tracked: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn insert<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>>;
+
+    fn update<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>>;
+
+    fn delete<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>>;
+
+    fn save_state(&self) -> BoxFuture<'_, Result<Box<dyn Any + Send>, DbError>>;
+
+    fn restore_state(&self, state: Box<dyn Any + Send>) -> BoxFuture<'_, ()>;
+}
+
+impl<T> Tracked for SharedEntity<T>
+where
+    T: DbData + Send + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn insert<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>> {
+        Box::pin(async move { self.lock().await.insert(conn).await })
+    }
+
+    fn update<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>> {
+        Box::pin(async move { self.lock().await.update(conn).await })
+    }
+
+    fn delete<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>> {
+        Box::pin(async move { self.lock().await.delete(conn).await })
+    }
+
+    fn save_state(&self) -> BoxFuture<'_, Result<Box<dyn Any + Send>, DbError>> {
+        Box::pin(async move {
+            let state = self.lock().await.sync_state()?;
+            Ok(Box::new(state) as Box<dyn Any + Send>)
+        })
+    }
+
+    fn restore_state(&self, state: Box<dyn Any + Send>) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Ok(state) = state.downcast::<SyncState<T>>() {
+                self.lock().await.restore_sync_state(*state);
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryState {
+    New,
+    Loaded,
+    Removed,
+    Discarded,
+}
+
+struct Entry {
+    entity: Box<dyn Tracked>,
+    state: EntryState,
+}
+
+trait JoinChange: Send + Sync {
+    fn flush<'a>(&'a self, conn: &'a Connection) -> BoxFuture<'a, Result<(), DbError>>;
+}
+
+struct JoinItems<'a, K, B>
+where
+    K: DbKey,
+    B: DbData,
+{
+    join: &'a DbJoin<K>,
+    items: Vec<SharedEntity<B>>,
+    add: bool,
+}
+
+impl<'a, K, B> JoinChange for JoinItems<'a, K, B>
+where
+    K: DbKey,
+    B: DbData + Send + 'static,
+{
+    fn flush<'b>(&'b self, conn: &'b Connection) -> BoxFuture<'b, Result<(), DbError>> {
+        Box::pin(async move {
+            // keys are read when flushing, as items inserted by the session get them on insert
+            let mut items_id = Vec::with_capacity(self.items.len());
+            for item in &self.items {
+                items_id.push(item.lock().await.id.clone());
+            }
+            if items_id.is_empty() {
+                Ok(())
+            } else if self.add {
+                self.join
+                    .insert_join_rows::<B>(conn, &items_id.iter().collect::<Vec<&B::Id>>())
+                    .await
+            } else {
+                self.join.delete_join_rows::<B>(conn, Some(&items_id)).await
+            }
+        })
+    }
+}
+
+fn entries_in(entries: &[Entry], state: EntryState) -> impl Iterator<Item = &dyn Tracked> {
+    entries
+        .iter()
+        .filter(move |entry| entry.state == state)
+        .map(|entry| entry.entity.as_ref())
+}
+
+async fn flush(
+    entries: &[Entry],
+    join_changes: &[Box<dyn JoinChange + '_>],
+    conn: &Connection,
+) -> Result<(), DbError> {
+    for entry in entries_in(entries, EntryState::New) {
+        entry.insert(conn).await?;
+    }
+    for entry in entries_in(entries, EntryState::Loaded) {
+        entry.update(conn).await?;
+    }
+    for join_change in join_changes {
+        join_change.flush(conn).await?;
+    }
+    for entry in entries_in(entries, EntryState::Removed) {
+        entry.delete(conn).await?;
+    }
+    Ok(())
+}
+
+type EntityKey = (&'static str, String);
+
+fn entity_key<T: DbData>(id: &T::Id) -> EntityKey {
+    (T::table_name(), format!("{:?}", id))
+}
+
+/// An identity map and unit of work over a connection.
+///
+/// Loaded entities are cached by table and key, so every lookup of the same record returns
+/// the same instance. New entities, removed entities and join changes are registered too.
+///
+/// On [commit](#method.commit) all of them are written in a single transaction.
+/// ## Example
+/// ```ignore
+/// let mut session = Session::new(&mut db_conn);
+/// let user = session.find::<User>(&user_id).await?.unwrap();
+/// let groups = DbEntity::<Group>::find_all(session.connection(), filter, None, 0, -1).await?;
+/// for group in session.attach_all(groups)? {
+///     group.lock().await.data.members += 1;
+/// }
+/// user.lock().await.data.last_seen = now;
+/// let role = session.register_new(DbEntity::from_data(role_data));
+/// session.add_join_items(&user_roles, &[&role]);
+/// session.commit().await?;
+/// ```
+pub struct Session<'a> {
+    conn: &'a mut Connection,
+    keys: HashMap<EntityKey, usize>,
+    entries: Vec<Entry>,
+    join_changes: Vec<Box<dyn JoinChange + 'a>>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self {
+            conn,
+            keys: HashMap::new(),
+            entries: vec![],
+            join_changes: vec![],
+        }
+    }
+
+    /// The wrapped connection, used to load entities to [attach](#method.attach)
+    pub fn connection(&self) -> &Connection {
+        self.conn
+    }
+
+    fn cached<T>(&self, key: &EntityKey) -> Result<Option<SharedEntity<T>>, DbError>
+    where
+        T: DbData + Send + 'static,
+    {
+        match self.keys.get(key) {
+            Some(&idx) => match self.entries[idx]
+                .entity
+                .as_any()
+                .downcast_ref::<SharedEntity<T>>()
+            {
+                Some(entity) => Ok(Some(entity.clone())),
+                None => Err(DbError::new(
+                    &format!("{}:{} is tracked with another type", key.0, key.1),
+                    None,
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn track<T>(&mut self, entity: DbEntity<T>, state: EntryState) -> SharedEntity<T>
+    where
+        T: DbData + Send + 'static,
+    {
+        let entity = Arc::new(Mutex::new(entity));
+        self.entries.push(Entry {
+            entity: Box::new(entity.clone()),
+            state,
+        });
+        entity
+    }
+
+    fn track_loaded<T>(&mut self, key: EntityKey, entity: DbEntity<T>) -> SharedEntity<T>
+    where
+        T: DbData + Send + 'static,
+    {
+        self.keys.insert(key, self.entries.len());
+        self.track(entity, EntryState::Loaded)
+    }
+
+    /// The entity with the key *id*, read from the database the first time only
+    pub async fn find<T>(&mut self, id: &T::Id) -> Result<Option<SharedEntity<T>>, DbError>
+    where
+        T: DbData + Send + 'static,
+    {
+        let key = entity_key::<T>(id);
+        if let Some(entity) = self.cached(&key)? {
+            return Ok(Some(entity));
+        }
+        let id_condition = dbkey::key_condition(T::ID_COLUMNS, None, 1);
+        let found = DbEntity::<T>::find_by(self.conn, (&id_condition, &id.params())).await?;
+        Ok(found.map(|entity| self.track_loaded(key, entity)))
+    }
+
+    /// Tracks an entity loaded from the database (such as by find_all or DbJoin::fetch), so it
+    /// is updated on commit when modified.
+    ///
+    /// When the record is already tracked the tracked instance is returned and *entity* is
+    /// discarded, so changes not yet committed are kept.
+    pub fn attach<T>(&mut self, entity: DbEntity<T>) -> Result<SharedEntity<T>, DbError>
+    where
+        T: DbData + Send + 'static,
+    {
+        let key = entity_key::<T>(&entity.id);
+        match self.cached(&key)? {
+            Some(tracked) => Ok(tracked),
+            None => Ok(self.track_loaded(key, entity)),
+        }
+    }
+
+    /// Registers a new entity, inserted on commit
+    pub fn register_new<T>(&mut self, entity: DbEntity<T>) -> SharedEntity<T>
+    where
+        T: DbData + Send + 'static,
+    {
+        self.track(entity, EntryState::New)
+    }
+
+    /// Registers the removal of an entity, deleted on commit. Removing a new entity
+    /// cancels its insertion.
+    pub fn register_removed<T>(&mut self, entity: &SharedEntity<T>)
+    where
+        T: DbData + Send + 'static,
+    {
+        let tracked = self.entries.iter_mut().find(|entry| {
+            entry
+                .entity
+                .as_any()
+                .downcast_ref::<SharedEntity<T>>()
+                .is_some_and(|tracked| Arc::ptr_eq(tracked, entity))
+        });
+        match tracked {
+            Some(entry) if entry.state == EntryState::New => entry.state = EntryState::Discarded,
+            Some(entry) => entry.state = EntryState::Removed,
+            None => {
+                self.entries.push(Entry {
+                    entity: Box::new(entity.clone()),
+                    state: EntryState::Removed,
+                });
+            }
+        }
+    }
+
+    /// Registers the addition of *items* to a join, as in
+    /// [DbJoin::add_items](struct.DbJoin.html#method.add_items)
+    pub fn add_join_items<K, B>(&mut self, join: &'a DbJoin<K>, items: &[&SharedEntity<B>])
+    where
+        K: DbKey,
+        B: DbData + Send + 'static,
+    {
+        self.join_changes.push(Box::new(JoinItems {
+            join,
+            items: items.iter().map(|&item| item.clone()).collect(),
+            add: true,
+        }));
+    }
+
+    /// Registers the removal of *items* from a join, as in
+    /// [DbJoin::remove_items](struct.DbJoin.html#method.remove_items)
+    pub fn remove_join_items<K, B>(&mut self, join: &'a DbJoin<K>, items: &[&SharedEntity<B>])
+    where
+        K: DbKey,
+        B: DbData + Send + 'static,
+    {
+        self.join_changes.push(Box::new(JoinItems {
+            join,
+            items: items.iter().map(|&item| item.clone()).collect(),
+            add: false,
+        }));
+    }
+
+    /// Tracks all the entities, as in [attach](#method.attach)
+    pub fn attach_all<T>(
+        &mut self,
+        entities: Vec<DbEntity<T>>,
+    ) -> Result<Vec<SharedEntity<T>>, DbError>
+    where
+        T: DbData + Send + 'static,
+    {
+        entities
+            .into_iter()
+            .map(|entity| self.attach(entity))
+            .collect()
+    }
+
+    /// Writes all the registered changes in a single transaction: new entities are inserted,
+    /// then changed entities are updated, join items are added or removed and finally
+    /// removed entities are deleted, each group in registration order.
+    ///
+    /// On the first error (such as a version conflict) the transaction is rolled back and the
+    /// keys, versions and loaded documents of all the entities are restored, so their changes
+    /// are still pending. New entities get back their documents as registered. Hooks such as
+    /// [before_update](trait.DbData.html#method.before_update) only read the entities, but
+    /// their side effects outside the transaction are not undone and they run again on the
+    /// next commit.
+    pub async fn commit(self) -> Result<(), DbError> {
+        let mut states = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            states.push(entry.entity.save_state().await?);
+        }
+        let tx = self.conn.begin().await?;
+        let result = match flush(&self.entries, &self.join_changes, &tx).await {
+            Ok(()) => tx.commit().await,
+            Err(e) => {
+                // the error which caused the rollback is returned, even if the rollback fails
+                let _ = tx.rollback().await;
+                Err(e)
+            }
+        };
+        if result.is_err() {
+            for (entry, state) in self.entries.iter().zip(states) {
+                entry.entity.restore_state(state).await;
+            }
+        }
+        result
+    }
+}
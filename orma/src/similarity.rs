@@ -0,0 +1,228 @@
+use crate::{
+    check_identifier, dbentity, json_path_expr, Cast, Connection, DbData, DbEntity, DbError, DbKey,
+    ToSql,
+};
+
+/// How two texts are compared by [DbEntity::similar](struct.DbEntity.html#method.similar)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimilarityMode {
+    /// Similarity of the whole texts (`similarity` function and `%` operator)
+    Similarity,
+    /// Greatest similarity between the searched text and any extent of ordered words of the field
+    /// (`word_similarity` function and `<%` operator), suited for partial names
+    WordSimilarity,
+}
+
+/// Options of [DbEntity::similar](struct.DbEntity.html#method.similar)
+pub struct SimilarityOptions<'a> {
+    /// Optional filter tuple, as in find_all
+    pub filter: Option<(&'a str, &'a [&'a (dyn ToSql + Sync)])>,
+    pub mode: SimilarityMode,
+    /// Minimum score of the results. When None the `%` and `<%` operators are used, so the
+    /// pg_trgm threshold settings apply and trigram indexes can be used.
+    pub threshold: Option<f32>,
+    pub offset: i64,
+    /// Maximum number of results. A negative limit returns all of them.
+    pub limit: i64,
+}
+
+impl<'a> Default for SimilarityOptions<'a> {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            mode: SimilarityMode::Similarity,
+            threshold: None,
+            offset: 0,
+            limit: -1,
+        }
+    }
+}
+
+/// An entity with its similarity score
+pub struct SimilarityResult<T>
+where
+    T: DbData,
+{
+    pub entity: DbEntity<T>,
+    /// Score between 0 and 1; results are sorted by descending score
+    pub score: f32,
+}
+
+/// Returns the DDL statement creating the trigram (gin_trgm_ops) expression index over the
+/// document field at *path*, as used by similarity searches. The pg_trgm extension must be
+/// installed in the database.
+///
+/// The index is named after the table and *path*, so an error is returned when they do not
+/// make a valid identifier.
+///
+/// ## Example
+/// ```ignore
+/// conn.batch_execute(&trigram_index_ddl::<User>("user_name")?).await?;
+/// ```
+pub fn trigram_index_ddl<T: DbData>(path: &str) -> Result<String, DbError> {
+    dbentity::check_names::<T>()?;
+    let table_name = T::table_name();
+    let index_name = format!(
+        "{short_name}_{field}_trgm_ix",
+        short_name = table_name.rsplit('.').next().unwrap_or(table_name),
+        field = path.replace('.', "_"),
+    );
+    Ok(format!(
+        "CREATE INDEX IF NOT EXISTS {index_name} ON {table_name} USING gin (({expr}) gin_trgm_ops)",
+        index_name = check_identifier(&index_name)?,
+        table_name = table_name,
+        expr = json_path_expr(T::DATA_COLUMN, path, Cast::Text),
+    ))
+}
+
+fn similarity_sql<T: DbData>(path: &str, options: &SimilarityOptions) -> String {
+    let filter_len = options.filter.map_or(0, |(_, filter_val)| filter_val.len());
+    // ->> binds looser than the % operator
+    let expr = format!("({})", json_path_expr(T::DATA_COLUMN, path, Cast::Text));
+    let text = format!("${}", filter_len + 1);
+    let score = match options.mode {
+        SimilarityMode::Similarity => format!("similarity({}, {})", expr, text),
+        SimilarityMode::WordSimilarity => format!("word_similarity({}, {})", text, expr),
+    };
+    let (condition, next_param) = match (options.threshold, options.mode) {
+        (Some(_), _) => (format!("{} >= ${}", score, filter_len + 2), filter_len + 3),
+        (None, SimilarityMode::Similarity) => (format!("{} % {}", expr, text), filter_len + 2),
+        (None, SimilarityMode::WordSimilarity) => (format!("{} <% {}", text, expr), filter_len + 2),
+    };
+    format!(
+        "SELECT {columns}, {score} AS similarity_score FROM {table_name} \
+         WHERE {condition}{filter} \
+         ORDER BY similarity_score DESC OFFSET ${offset}{limit}",
+        columns = dbentity::entity_columns::<T>(None),
+        score = score,
+        table_name = T::table_name(),
+        condition = condition,
+        filter = match options.filter {
+            Some((filter, _)) => format!(
+                " AND ({})",
+                dbentity::promote_fields(filter, T::DATA_COLUMN, T::promoted_fields())
+            ),
+            None => String::from(""),
+        },
+        offset = next_param,
+        limit = if options.limit < 0 {
+            String::from("")
+        } else {
+            format!(" LIMIT ${}", next_param + 1)
+        },
+    )
+}
+
+impl<T> DbEntity<T>
+where
+    T: DbData,
+{
+    /// Typo tolerant search of the records whose document field at *path* is similar to *text*,
+    /// using pg_trgm trigram matching. Results are sorted by descending score.
+    /// ## Example
+    /// ```ignore
+    /// let results = DbEntity::<User>::similar(
+    ///     db_conn,
+    ///     "user_name",
+    ///     "jhon",
+    ///     &SimilarityOptions {
+    ///         mode: SimilarityMode::WordSimilarity,
+    ///         limit: 10,
+    ///         ..SimilarityOptions::default()
+    ///     },
+    /// )
+    /// .await?;
+    /// ```
+    pub async fn similar(
+        conn: &Connection,
+        path: &str,
+        text: &str,
+        options: &SimilarityOptions<'_>,
+    ) -> Result<Vec<SimilarityResult<T>>, DbError> {
+        dbentity::check_names::<T>()?;
+        let prepared_s = conn.prepare(&similarity_sql::<T>(path, options)).await?;
+        let mut params: Vec<&(dyn ToSql + Sync)> = match options.filter {
+            Some((_, filter_val)) => filter_val.to_vec(),
+            None => vec![],
+        };
+        params.push(&text);
+        if let Some(threshold) = &options.threshold {
+            params.push(threshold);
+        }
+        params.push(&options.offset);
+        if options.limit >= 0 {
+            params.push(&options.limit);
+        }
+
+        let score_idx = T::Id::COLUMNS + 2 + T::EXTRA_COLUMNS.len();
+        conn.query(&prepared_s, &params)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(SimilarityResult {
+                    entity: DbEntity::from_row(row)?,
+                    score: row.get(score_idx),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::*;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize)]
+    struct Person {}
+
+    impl DbData for Person {
+        type Id = Uuid;
+
+        fn table_name() -> &'static str {
+            "intrared.people"
+        }
+        fn id(&self) -> Option<Uuid> {
+            None
+        }
+        fn version(&self) -> Option<i32> {
+            None
+        }
+        fn set_id(&mut self, _uuid: Uuid) {}
+        fn set_version(&mut self, _version: i32) {}
+    }
+
+    #[test]
+    fn test_trigram_index_ddl() {
+        assert_eq!(
+            trigram_index_ddl::<Person>("name.last").unwrap(),
+            "CREATE INDEX IF NOT EXISTS people_name_last_trgm_ix ON intrared.people \
+             USING gin ((data->'name'->>'last') gin_trgm_ops)"
+        );
+        assert!(trigram_index_ddl::<Person>("name; DROP TABLE people").is_err());
+        assert!(trigram_index_ddl::<Person>("first name").is_err());
+    }
+
+    #[test]
+    fn test_similarity_sql() {
+        let sql = similarity_sql::<Person>("name", &SimilarityOptions::default());
+        assert!(sql.contains("similarity((data->>'name'), $1) AS similarity_score"));
+        assert!(sql.contains("WHERE (data->>'name') % $1 ORDER BY"));
+        assert!(sql.ends_with("OFFSET $2"));
+
+        let sql = similarity_sql::<Person>(
+            "name",
+            &SimilarityOptions {
+                filter: Some(("data->>'city' = $1", &[&"Rome"])),
+                mode: SimilarityMode::WordSimilarity,
+                threshold: Some(0.5),
+                limit: 10,
+                ..SimilarityOptions::default()
+            },
+        );
+        assert!(sql
+            .contains("WHERE word_similarity($2, (data->>'name')) >= $3 AND (data->>'city' = $1)"));
+        assert!(sql.ends_with("OFFSET $4 LIMIT $5"));
+    }
+}
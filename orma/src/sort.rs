@@ -0,0 +1,189 @@
+use crate::{json_path_expr, quote_ident, Cast, DbError};
+
+/// Placement of NULL values in a sorting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SortExpr {
+    Path(String, Cast),
+    Raw(String),
+}
+
+/// A sorting item over a document field.
+///
+/// The field value is casted before comparison, so numbers, dates and booleans
+/// are not sorted as text.
+/// ## Example
+/// ```rust
+/// use orma::{Cast, Sort};
+///
+/// let sorting = [
+///     Sort::desc("age").cast(Cast::Numeric).nulls_last(),
+///     Sort::asc("last_name").collate("C"),
+/// ];
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    expr: SortExpr,
+    descending: bool,
+    nulls: Option<Nulls>,
+    collation: Option<String>,
+}
+
+impl Sort {
+    fn new(expr: SortExpr, descending: bool) -> Self {
+        Self {
+            expr,
+            descending,
+            nulls: None,
+            collation: None,
+        }
+    }
+
+    /// Ascending sorting over the text value at *path* (dot separated, such as "address.city")
+    pub fn asc(path: &str) -> Self {
+        Self::new(SortExpr::Path(path.to_owned(), Cast::Text), false)
+    }
+
+    /// Descending sorting over the text value at *path* (dot separated, such as "address.city")
+    pub fn desc(path: &str) -> Self {
+        Self::new(SortExpr::Path(path.to_owned(), Cast::Text), true)
+    }
+
+    /// Sorting over a raw SQL expression (such as "data->>'name' DESC"), used as is.
+    ///
+    /// Never build raw sortings from untrusted input: use [parse_allowed](#method.parse_allowed) instead.
+    pub fn raw(expr: &str) -> Self {
+        Self::new(SortExpr::Raw(expr.to_owned()), false)
+    }
+
+    /// Builds sortings from untrusted input (such as a URL query parameter) against an
+    /// allow-list of sortings.
+    ///
+    /// *input* is a comma separated list of document paths, each optionally prefixed with
+    /// "-" for descending order (such as "-age,last_name"). Every path must be the path of one
+    /// of the *allowed* sortings, whose cast, collation and nulls placement are kept.
+    /// ## Example
+    /// ```rust
+    /// use orma::{Cast, Sort};
+    ///
+    /// let allowed = [Sort::asc("name"), Sort::asc("age").cast(Cast::Integer)];
+    /// let sorting = Sort::parse_allowed("-age,name", &allowed).unwrap();
+    /// assert_eq!(sorting, vec![Sort::desc("age").cast(Cast::Integer), Sort::asc("name")]);
+    /// assert!(Sort::parse_allowed("email", &allowed).is_err());
+    /// ```
+    pub fn parse_allowed(input: &str, allowed: &[Sort]) -> Result<Vec<Sort>, DbError> {
+        input
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                let (path, descending) = match item.strip_prefix('-') {
+                    Some(path) => (path, true),
+                    None => (item.strip_prefix('+').unwrap_or(item), false),
+                };
+                allowed
+                    .iter()
+                    .find(
+                        |sort| matches!(&sort.expr, SortExpr::Path(allowed, _) if allowed == path),
+                    )
+                    .map(|sort| Sort {
+                        descending,
+                        ..sort.clone()
+                    })
+                    .ok_or_else(|| {
+                        DbError::new(&format!("Sorting by {:?} is not allowed", path), None)
+                    })
+            })
+            .collect()
+    }
+
+    /// Casts the value before sorting
+    pub fn cast(mut self, cast: Cast) -> Self {
+        if let SortExpr::Path(_, ref mut path_cast) = self.expr {
+            *path_cast = cast;
+        }
+        self
+    }
+
+    /// Sorts NULL values before the others
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls = Some(Nulls::First);
+        self
+    }
+
+    /// Sorts NULL values after the others
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls = Some(Nulls::Last);
+        self
+    }
+
+    /// Compares values with the given collation (such as "C" or "en_US")
+    pub fn collate(mut self, collation: &str) -> Self {
+        self.collation = Some(collation.to_owned());
+        self
+    }
+
+    /// The ORDER BY item, where the document column is *data_column* prefixed with the
+    /// optional table *alias*
+    pub fn sql(&self, data_column: &str, alias: Option<&str>) -> String {
+        let mut sql = match (&self.expr, alias) {
+            (SortExpr::Path(path, cast), Some(alias)) => {
+                json_path_expr(&format!("{}.{}", alias, data_column), path, *cast)
+            }
+            (SortExpr::Path(path, cast), None) => json_path_expr(data_column, path, *cast),
+            (SortExpr::Raw(expr), Some(alias)) => format!("{}.{}", alias, expr),
+            (SortExpr::Raw(expr), None) => expr.clone(),
+        };
+        if let Some(collation) = &self.collation {
+            sql.push_str(&format!(" COLLATE {}", quote_ident(collation)));
+        }
+        if self.descending {
+            sql.push_str(" DESC");
+        }
+        match self.nulls {
+            Some(Nulls::First) => sql.push_str(" NULLS FIRST"),
+            Some(Nulls::Last) => sql.push_str(" NULLS LAST"),
+            None => (),
+        }
+        sql
+    }
+}
+
+impl From<&str> for Sort {
+    fn from(expr: &str) -> Self {
+        Sort::raw(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_sql() {
+        assert_eq!(Sort::asc("name").sql("data", None), "data->>'name'");
+        assert_eq!(
+            Sort::desc("stats.age")
+                .cast(Cast::Numeric)
+                .nulls_last()
+                .sql("data", Some("a")),
+            "(a.data->'stats'->>'age')::numeric DESC NULLS LAST"
+        );
+        assert_eq!(
+            Sort::asc("name")
+                .collate("C")
+                .nulls_first()
+                .sql("doc", None),
+            "doc->>'name' COLLATE \"C\" NULLS FIRST"
+        );
+        assert_eq!(
+            Sort::from("data->>'name' DESC").sql("data", Some("a")),
+            "a.data->>'name' DESC"
+        );
+    }
+}
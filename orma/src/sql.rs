@@ -0,0 +1,162 @@
+use crate::DbError;
+
+/// SQL type a JSON value is casted to when it's extracted from a document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cast {
+    Text,
+    Integer,
+    BigInt,
+    Numeric,
+    Double,
+    Boolean,
+    Date,
+    Timestamp,
+    TimestampTz,
+}
+
+impl Cast {
+    /// The name of the SQL type
+    pub fn sql_type(self) -> &'static str {
+        match self {
+            Cast::Text => "text",
+            Cast::Integer => "integer",
+            Cast::BigInt => "bigint",
+            Cast::Numeric => "numeric",
+            Cast::Double => "double precision",
+            Cast::Boolean => "boolean",
+            Cast::Date => "date",
+            Cast::Timestamp => "timestamp",
+            Cast::TimestampTz => "timestamptz",
+        }
+    }
+}
+
+/// Quotes *value* as a SQL string literal
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Quotes *name* as a SQL identifier
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn is_plain_identifier(part: &str) -> bool {
+    let mut chars = part.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && part.len() <= 63
+}
+
+fn is_quoted_identifier(part: &str) -> bool {
+    part.len() > 2
+        && part.starts_with('"')
+        && part.ends_with('"')
+        && !part.contains('\0')
+        && !part[1..part.len() - 1].replace("\"\"", "").contains('"')
+}
+
+/// Splits *name* on the dots that are not inside a quoted identifier
+fn split_qualified(name: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in name.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => {
+                parts.push(&name[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&name[start..]);
+    parts
+}
+
+/// Checks that *name* is a valid, optionally schema-qualified, identifier (such as
+/// "intrared.users" or "public.\"User Data\"") so that it can be safely interpolated into
+/// a query. Plain names must be made of letters, digits, '_' and '$', otherwise they must
+/// be quoted.
+///
+/// ## Example
+/// ```rust
+/// use orma::check_identifier;
+///
+/// assert!(check_identifier("intrared.users").is_ok());
+/// assert!(check_identifier("users; DROP TABLE users").is_err());
+/// ```
+pub fn check_identifier(name: &str) -> Result<&str, DbError> {
+    let parts = split_qualified(name);
+    if parts.len() <= 3
+        && parts
+            .iter()
+            .all(|part| is_plain_identifier(part) || is_quoted_identifier(part))
+    {
+        Ok(name)
+    } else {
+        Err(DbError::new(
+            &format!("Invalid identifier {:?}", name),
+            None,
+        ))
+    }
+}
+
+/// Expression extracting the value at *path* (dot separated, such as "address.city")
+/// from the document column *data_column*, casted to *cast*.
+///
+/// ## Example
+/// ```rust
+/// use orma::{json_path_expr, Cast};
+///
+/// assert_eq!(
+///     json_path_expr("data", "address.zip", Cast::Integer),
+///     "(data->'address'->>'zip')::integer"
+/// );
+/// ```
+pub fn json_path_expr(data_column: &str, path: &str, cast: Cast) -> String {
+    let mut segments: Vec<String> = path.split('.').map(quote_literal).collect();
+    let last = segments.pop().unwrap_or_default();
+    let text_expr = if segments.is_empty() {
+        format!("{}->>{}", data_column, last)
+    } else {
+        format!("{}->{}->>{}", data_column, segments.join("->"), last)
+    };
+    match cast {
+        Cast::Text => text_expr,
+        _ => format!("({})::{}", text_expr, cast.sql_type()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_path_expr() {
+        assert_eq!(json_path_expr("data", "name", Cast::Text), "data->>'name'");
+        assert_eq!(
+            json_path_expr("a.doc", "stock.qty", Cast::Numeric),
+            "(a.doc->'stock'->>'qty')::numeric"
+        );
+        assert_eq!(json_path_expr("data", "it's", Cast::Text), "data->>'it''s'");
+    }
+
+    #[test]
+    fn test_check_identifier() {
+        assert!(check_identifier("users").is_ok());
+        assert!(check_identifier("intrared.users").is_ok());
+        assert!(check_identifier("db.intrared.\"User.Data\"").is_ok());
+        assert!(check_identifier("\"say \"\"hi\"\"\"").is_ok());
+        assert!(check_identifier("").is_err());
+        assert!(check_identifier("intrared.").is_err());
+        assert!(check_identifier("1users").is_err());
+        assert!(check_identifier("users u").is_err());
+        assert!(check_identifier("\"users\" \"u\"").is_err());
+        assert!(check_identifier("users;--").is_err());
+        assert!(check_identifier("a.b.c.d").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
 CREATE SCHEMA intrared;
 CREATE TABLE intrared.groups (
   id uuid NOT NULL,
@@ -17,6 +18,30 @@ CREATE TABLE intrared.roles (
   data jsonb,
   version integer NOT NULL
 );
+CREATE TABLE intrared.notes (
+  uid uuid NOT NULL,
+  rev integer NOT NULL,
+  doc jsonb,
+  edited_by text
+);
+CREATE TABLE intrared.products (
+  code text NOT NULL,
+  region text NOT NULL,
+  data jsonb,
+  version integer NOT NULL
+);
+CREATE TABLE intrared.tickets (
+  id bigint GENERATED BY DEFAULT AS IDENTITY,
+  product_code text,
+  product_region text,
+  data jsonb,
+  version integer NOT NULL
+);
+CREATE TABLE intrared.attachments (
+  id uuid DEFAULT gen_random_uuid() NOT NULL,
+  data jsonb,
+  version integer NOT NULL
+);
 CREATE TABLE intrared.users (
   id uuid NOT NULL,
   data jsonb,
@@ -31,12 +56,24 @@ ADD
 ALTER TABLE ONLY intrared.r_user_group
 ADD
   CONSTRAINT r_user_group_pkey PRIMARY KEY (id_user, id_group);
+ALTER TABLE ONLY intrared.notes
+ADD
+  CONSTRAINT notes_pkey PRIMARY KEY (uid);
+ALTER TABLE ONLY intrared.products
+ADD
+  CONSTRAINT products_pkey PRIMARY KEY (code, region);
 ALTER TABLE ONLY intrared.roles
 ADD
   CONSTRAINT roles_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY intrared.tickets
+ADD
+  CONSTRAINT tickets_pkey PRIMARY KEY (id);
 ALTER TABLE ONLY intrared.users
 ADD
   CONSTRAINT users_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY intrared.attachments
+ADD
+  CONSTRAINT attachments_pkey PRIMARY KEY (id);
 CREATE INDEX fki_rg_id_group_fk ON intrared.r_role_group USING btree (id_group);
 CREATE INDEX fki_rg_id_role_fk ON intrared.r_role_group USING btree (id_role);
 CREATE INDEX fki_ug_id_group_fk ON intrared.r_user_group USING btree (id_group);
@@ -60,9 +97,18 @@ ADD
 ALTER TABLE ONLY intrared.r_user_group
 ADD
   CONSTRAINT id_group_fk FOREIGN KEY (id_group) REFERENCES intrared.groups(id) ON DELETE CASCADE;
+ALTER TABLE ONLY intrared.tickets
+ADD
+  CONSTRAINT product_fk FOREIGN KEY (product_code, product_region) REFERENCES intrared.products(code, region) ON DELETE CASCADE;
 ALTER TABLE ONLY intrared.r_role_group
 ADD
   CONSTRAINT id_role_fk FOREIGN KEY (id_role) REFERENCES intrared.roles(id) ON DELETE CASCADE;
 ALTER TABLE ONLY intrared.r_user_group
 ADD
-  CONSTRAINT id_user_fk FOREIGN KEY (id_user) REFERENCES intrared.users(id) ON DELETE CASCADE;
\ No newline at end of file
+  CONSTRAINT id_user_fk FOREIGN KEY (id_user) REFERENCES intrared.users(id) ON DELETE CASCADE;
+CREATE FUNCTION intrared.notes_stamp() RETURNS trigger AS $$ BEGIN
+  NEW.doc = jsonb_set(NEW.doc, '{stamp}', to_jsonb(NEW.rev));
+  NEW.edited_by = current_user;
+  RETURN NEW;
+END $$ LANGUAGE plpgsql;
+CREATE TRIGGER notes_stamp BEFORE INSERT OR UPDATE ON intrared.notes FOR EACH ROW EXECUTE FUNCTION intrared.notes_stamp();
\ No newline at end of file
@@ -0,0 +1,24 @@
+use orma::*;
+use serde::{Deserialize, Serialize};
+
+#[orma_obj(
+    table = "intrared.notes",
+    id_columns = "uid",
+    version_column = "rev",
+    data_column = "doc",
+    search_config = "english"
+)]
+#[derive(Serialize, Deserialize)]
+pub struct Note {
+    #[orma(search)]
+    pub text: String,
+    pub stamp: Option<i32>,
+    #[orma(column)]
+    pub edited_by: Option<String>,
+}
+
+#[orma_obj(table = "intrared.attachments", id_strategy = "database")]
+#[derive(Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_name: String,
+}
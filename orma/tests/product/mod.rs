@@ -0,0 +1,29 @@
+use orma::*;
+use serde::{Deserialize, Serialize};
+
+#[orma_obj(
+    table = "intrared.products",
+    id_type = "(String, String)",
+    id_columns = "code, region"
+)]
+#[derive(Serialize, Deserialize)]
+pub struct Product {
+    pub name: String,
+    pub category: String,
+    pub price: f64,
+    pub tags: Vec<String>,
+}
+
+#[orma_obj(table = "intrared.tickets", id_type = "i64", id_strategy = "database")]
+#[derive(Serialize, Deserialize)]
+pub struct Ticket {
+    pub title: String,
+}
+
+pub fn product_tickets(product: &DbEntity<Product>) -> Result<DbJoin<(String, String)>, DbError> {
+    JoinBuilder::new(&product.data)
+        .with_source_fk("product_code, product_region")
+        .with_target(Ticket::table_name())
+        .with_sorting(&[Sort::asc("title")])
+        .build()
+}
@@ -0,0 +1,19 @@
+use orma::*;
+use serde::{Deserialize, Serialize};
+
+#[orma_obj(table = "intrared.roles")]
+#[derive(Serialize, Deserialize)]
+pub struct Role {
+    pub app: String,
+    #[orma(promoted)]
+    pub name: String,
+}
+
+impl Role {
+    pub async fn find_by_name(
+        db_conn: &Connection,
+        name: &str,
+    ) -> Result<Option<DbEntity<Role>>, DbError> {
+        DbEntity::<Role>::find_by(db_conn, ("data->>'name'=$1", &[&name])).await
+    }
+}
@@ -1,9 +1,24 @@
 pub mod group;
+pub mod note;
+pub mod product;
+pub mod role;
 pub mod user;
 
+use futures::FutureExt;
 use group::*;
-use orma::{new_data, Connection, DbEntity, DbError};
+use note::*;
+use orma::{
+    named_params, new_data, promoted_fields_ddl, search_index_ddl, trigram_index_ddl, Cast,
+    Connection, DbEntity, DbError, Filter, IsolationLevel, JoinBuilder, Json, PatchOperation,
+    SearchOptions, Session, SimilarityMode, SimilarityOptions, Sort, Uuid,
+};
+use product::*;
+use role::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::env;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 use user::*;
 
 pub const SQL_INIT: &str = include_str!("db_setup.sql");
@@ -69,6 +84,25 @@ fn create_user(user_name: &str, email: &str) -> User {
     })
 }
 
+fn create_product(
+    code: &str,
+    region: &str,
+    name: &str,
+    category: &str,
+    price: f64,
+) -> DbEntity<Product> {
+    DbEntity::new(
+        (code.to_owned(), region.to_owned()),
+        0,
+        new_data!(Product, {
+            name: name.to_owned(),
+            category: category.to_owned(),
+            price,
+            tags: vec![],
+        }),
+    )
+}
+
 fn create_group(name: &str, description: &str) -> Group {
     new_data!(Group, {
         name: name.to_owned(),
@@ -90,14 +124,12 @@ async fn test_user_crud(connection: Connection) {
     user_entity.insert(&connection).await.unwrap();
     //assert_eq!(user_entity.id, user_entity.data.get_id().unwrap());
 
-    let find_result = User::find_by_user_name(&connection, &user_name).await;
-    assert!(
-        find_result.is_ok(),
-        format!("Failed to find user {}", &user_name)
-    );
+    let find_result = User::find_by_user_name(&connection, user_name).await;
+    assert!(find_result.is_ok(), "Failed to find user {}", &user_name);
     assert!(
         find_result.unwrap().is_some(),
-        format!("User {} not found", &user_name)
+        "User {} not found",
+        &user_name
     );
 
     assert!(
@@ -105,11 +137,12 @@ async fn test_user_crud(connection: Connection) {
         "Delete Failed"
     );
 
-    let find_after_delete_result = User::find_by_user_name(&connection, &user_name).await;
+    let find_after_delete_result = User::find_by_user_name(&connection, user_name).await;
 
     assert!(
         find_after_delete_result.unwrap().is_none(),
-        format!("User {} not deleted", &user_name)
+        "User {} not deleted",
+        &user_name
     );
 }
 
@@ -127,23 +160,22 @@ async fn test_group_crud(connection: Connection) {
 
     group_entity.insert(&conn).await.unwrap();
 
-    let find_result = Group::find_by_name(&conn, &group_name).await;
-    assert!(
-        find_result.is_ok(),
-        format!("Failed to find group {}", &group_name)
-    );
+    let find_result = Group::find_by_name(&conn, group_name).await;
+    assert!(find_result.is_ok(), "Failed to find group {}", &group_name);
     assert!(
         find_result.unwrap().is_some(),
-        format!("Group {} not found", &group_name)
+        "Group {} not found",
+        &group_name
     );
 
     assert!(group_entity.delete(&conn).await.is_ok(), "Delete Failed");
 
-    let find_after_delete_result = Group::find_by_name(&conn, &group_name).await;
+    let find_after_delete_result = Group::find_by_name(&conn, group_name).await;
 
     assert!(
         find_after_delete_result.unwrap().is_none(),
-        format!("Group {} not deleted", &group_name)
+        "Group {} not deleted",
+        &group_name
     );
 }
 
@@ -180,20 +212,16 @@ async fn test_user_group_join(connection: Connection) {
         DbEntity::find_all(&conn, None, None, 0, 1).await.unwrap();
     assert!(
         all_groups.len() == 1,
-        format!(
-            "There should be just 1 group. {} groups where found.",
-            all_groups.len()
-        )
+        "There should be just 1 group. {} groups where found.",
+        all_groups.len()
     );
 
     let all_groups: Vec<DbEntity<Group>> =
         DbEntity::find_all(&conn, None, None, 0, 2).await.unwrap();
     assert!(
         all_groups.len() == 1,
-        format!(
-            "There should be just 1 group. {} groups where found.",
-            all_groups.len()
-        )
+        "There should be just 1 group. {} groups where found.",
+        all_groups.len()
     );
 
     // println!("User entity id: {:?}", &user_entity1.data.id());
@@ -202,12 +230,10 @@ async fn test_user_group_join(connection: Connection) {
     let user1_groups_items: Vec<DbEntity<Group>> = user1_groups.fetch(&conn).await.unwrap();
     assert!(
         user1_groups_items.len() == 1,
-        format!(
-            "User should have just 1 associated group. {} groups where found.",
-            user1_groups_items.len()
-        )
+        "User should have just 1 associated group. {} groups where found.",
+        user1_groups_items.len()
     );
-    let ug_grp1_entity = user1_groups_items.get(0).unwrap();
+    let ug_grp1_entity = user1_groups_items.first().unwrap();
 
     assert_eq!(
         group_entity1.data.name, ug_grp1_entity.data.name,
@@ -227,32 +253,30 @@ async fn test_user_group_join(connection: Connection) {
         .add_items(&mut conn, &[&group_entity2])
         .await
         .unwrap();
-    user1_groups.sorting = vec!["data->>'name'".to_owned()];
+    user1_groups.sorting = vec![Sort::asc("name")];
 
     let user1_groups_items: Vec<DbEntity<Group>> = user1_groups.fetch(&conn).await.unwrap();
     assert!(
         user1_groups_items.len() == 2,
-        format!(
-            "User should have just 2 associated groups. {} groups where found.",
-            user1_groups_items.len()
-        )
+        "User should have just 2 associated groups. {} groups where found.",
+        user1_groups_items.len()
     );
     assert_eq!(
-        user1_groups_items.get(0).unwrap().data.name,
+        user1_groups_items.first().unwrap().data.name,
         group_name1,
         "Expected user name {}, but {} was found.",
         group_name1,
-        user1_groups_items.get(0).unwrap().data.name
+        user1_groups_items.first().unwrap().data.name
     );
-    user1_groups.sorting = vec!["data->>'name' DESC".to_owned()];
+    user1_groups.sorting = vec![Sort::desc("name")];
 
     let user1_groups_items: Vec<DbEntity<Group>> = user1_groups.fetch(&conn).await.unwrap();
     assert_eq!(
-        user1_groups_items.get(0).unwrap().data.name,
+        user1_groups_items.first().unwrap().data.name,
         group_name2,
         "Expected user name {}, but {} was found.",
         group_name2,
-        user1_groups_items.get(0).unwrap().data.name
+        user1_groups_items.first().unwrap().data.name
     );
 
     let user1_groups_items: Vec<DbEntity<Group>> = user1_groups
@@ -261,9 +285,1381 @@ async fn test_user_group_join(connection: Connection) {
         .unwrap();
     assert!(
         user1_groups_items.len() == 1,
-        format!(
-            "User should have just 1 associated groups. {} groups where found.",
-            user1_groups_items.len()
+        "User should have just 1 associated groups. {} groups where found.",
+        user1_groups_items.len()
+    );
+}
+
+#[orma::test]
+async fn test_promoted_fields(connection: Connection) {
+    let conn = connection.await;
+    for statement in promoted_fields_ddl::<Role>() {
+        conn.batch_execute(&statement).await.unwrap();
+    }
+
+    let mut role_entity = DbEntity::from_data(new_data!(Role, {
+        app: "orma".to_owned(),
+        name: "test_promoted_fields".to_owned(),
+    }));
+    role_entity.insert(&conn).await.unwrap();
+
+    let row = conn
+        .query_one(
+            "SELECT name FROM intrared.roles WHERE id = $1",
+            &[&role_entity.id],
         )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, String>(0), "test_promoted_fields");
+
+    let found = Role::find_by_name(&conn, "test_promoted_fields")
+        .await
+        .unwrap();
+    assert_eq!(found.unwrap().id, role_entity.id);
+
+    let roles: Vec<DbEntity<Role>> =
+        DbEntity::find_all(&conn, None, Some(&[Sort::desc("name")]), 0, -1)
+            .await
+            .unwrap();
+    assert_eq!(roles.len(), 1);
+}
+
+#[orma::test]
+async fn test_custom_keys(connection: Connection) {
+    let mut conn = connection.await;
+
+    let mut product_entity = DbEntity::new(
+        ("P01".to_owned(), "EU".to_owned()),
+        0,
+        new_data!(Product, {
+            name: "test_custom_keys".to_owned(),
+            category: "tools".to_owned(),
+            price: 10.0,
+            tags: vec![],
+        }),
+    );
+    product_entity.insert(&conn).await.unwrap();
+    product_entity.data.name = "test_custom_keys updated".to_owned();
+    product_entity.update(&conn).await.unwrap();
+    assert_eq!(product_entity.version, 2);
+
+    let found: DbEntity<Product> = DbEntity::find_by(&conn, ("code = $1", &[&"P01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.id, ("P01".to_owned(), "EU".to_owned()));
+    assert_eq!(found.data.name, "test_custom_keys updated");
+
+    let mut ticket_entity = DbEntity::from_data(new_data!(Ticket, {
+        title: "ticket 1".to_owned(),
+    }));
+    ticket_entity.insert(&conn).await.unwrap();
+    let ticket_id = ticket_entity.id;
+    assert!(ticket_id > 0);
+    assert_eq!(ticket_entity.data.orma_id, Some(ticket_id));
+
+    let tickets = product_tickets(&product_entity).unwrap();
+    tickets
+        .add_items(&mut conn, &[&ticket_entity])
+        .await
+        .unwrap();
+    let product_tickets: Vec<DbEntity<Ticket>> = tickets.fetch(&conn).await.unwrap();
+    assert_eq!(product_tickets.len(), 1);
+    assert_eq!(product_tickets[0].id, ticket_id);
+
+    tickets
+        .remove_items_by_id::<Ticket>(&mut conn, Some(&[ticket_id]))
+        .await
+        .unwrap();
+    let product_tickets: Vec<DbEntity<Ticket>> = tickets.fetch(&conn).await.unwrap();
+    assert!(product_tickets.is_empty());
+
+    // natural keys are not generated: they must be assigned before inserting
+    assert!(create_product("", "", "no key", "tools", 1.0)
+        .insert(&conn)
+        .await
+        .is_err());
+
+    product_entity.delete(&conn).await.unwrap();
+}
+
+#[orma::test]
+async fn test_column_names(connection: Connection) {
+    let conn = connection.await;
+
+    let mut note_entity = DbEntity::from_data(new_data!(Note, {
+        text: "test_column_names".to_owned(),
+        stamp: None,
+        edited_by: None,
+    }));
+    note_entity.insert(&conn).await.unwrap();
+    note_entity.data.text = "test_column_names updated".to_owned();
+    note_entity.update(&conn).await.unwrap();
+
+    let notes: Vec<DbEntity<Note>> = DbEntity::find_all(
+        &conn,
+        Some(("doc->>'text' LIKE $1", &[&"test_column_names%"])),
+        Some(&[Sort::asc("text")]),
+        0,
+        10,
+    )
+    .await
+    .unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].id, note_entity.id);
+    assert_eq!(notes[0].version, 2);
+
+    note_entity.delete(&conn).await.unwrap();
+    let note = DbEntity::<Note>::find_by(&conn, ("uid = $1", &[&note_entity.id]))
+        .await
+        .unwrap();
+    assert!(note.is_none());
+}
+
+#[orma::test]
+async fn test_database_uuid_keys(connection: Connection) {
+    let conn = connection.await;
+
+    let mut first = DbEntity::from_data(new_data!(Attachment, {
+        file_name: "first.txt".to_owned(),
+    }));
+    assert_eq!(first.id, Uuid::nil());
+    first.insert(&conn).await.unwrap();
+    assert_ne!(first.id, Uuid::nil());
+    assert_eq!(first.data.orma_id, Some(first.id));
+
+    let mut second = DbEntity::from_data(new_data!(Attachment, {
+        file_name: "second.txt".to_owned(),
+    }));
+    second.insert(&conn).await.unwrap();
+    assert_ne!(second.id, first.id);
+
+    let found = DbEntity::<Attachment>::find_by(&conn, ("id = $1", &[&first.id]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.data.file_name, "first.txt");
+
+    first.data.file_name = "renamed.txt".to_owned();
+    first.update(&conn).await.unwrap();
+    assert_eq!(first.version, 2);
+    first.delete(&conn).await.unwrap();
+    second.delete(&conn).await.unwrap();
+}
+
+#[orma::test]
+async fn test_returning(connection: Connection) {
+    let conn = connection.await;
+
+    let mut note_entity = DbEntity::from_data(new_data!(Note, {
+        text: "test_returning".to_owned(),
+        stamp: None,
+        edited_by: None,
+    }));
+    note_entity.insert(&conn).await.unwrap();
+    let current_user: String = conn
+        .query_one("SELECT current_user", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(note_entity.version, 1);
+    assert_eq!(note_entity.data.stamp, Some(1));
+    assert_eq!(
+        note_entity.data.edited_by.as_deref(),
+        Some(current_user.as_str())
+    );
+
+    note_entity.data.text = "test_returning updated".to_owned();
+    note_entity.update(&conn).await.unwrap();
+    assert_eq!(note_entity.version, 2);
+    assert_eq!(note_entity.data.stamp, Some(2));
+    assert_eq!(note_entity.data.orma_version, Some(2));
+
+    let found = DbEntity::<Note>::find_by(&conn, ("uid = $1", &[&note_entity.id]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.data.edited_by, Some(current_user));
+}
+
+#[orma::test]
+async fn test_select_as(connection: Connection) {
+    let conn = connection.await;
+
+    let mut user_entity =
+        DbEntity::from_data(create_user("test_select_as", "test_select_as@test.com"));
+    user_entity.insert(&conn).await.unwrap();
+    DbEntity::from_data(create_user("test_select_as2", "test_select_as2@test.com"))
+        .insert(&conn)
+        .await
+        .unwrap();
+
+    let user_names = DbEntity::<User>::select_as::<UserName>(
+        &conn,
+        &["user_name", "email"],
+        Some(("data->>'user_name' = $1", &[&"test_select_as"])),
+    )
+    .await
+    .unwrap();
+    assert_eq!(user_names.len(), 1);
+    assert_eq!(user_names[0].id, user_entity.id);
+    assert_eq!(user_names[0].version, 1);
+    assert_eq!(user_names[0].user_name, "test_select_as");
+    assert_eq!(user_names[0].email, "test_select_as@test.com");
+
+    let user_names = DbEntity::<User>::select_as::<UserName>(&conn, &["user_name", "email"], None)
+        .await
+        .unwrap();
+    assert_eq!(user_names.len(), 2);
+
+    // keys are named after the last path segment, so they must not collide
+    assert!(DbEntity::<User>::select_as::<UserName>(
+        &conn,
+        &["user_name", "alias.user_name"],
+        None
+    )
+    .await
+    .is_err());
+}
+
+#[derive(Deserialize)]
+struct ProductsByCategory {
+    category: String,
+    products: i64,
+    total: f64,
+    cheapest: f64,
+}
+
+#[orma::test]
+async fn test_aggregate(connection: Connection) {
+    let conn = connection.await;
+
+    for (code, category, price) in &[
+        ("A01", "tools", 10.0),
+        ("A02", "tools", 30.0),
+        ("A03", "garden", 5.5),
+        ("A04", "food", 2.0),
+    ] {
+        create_product(code, "EU", code, category, *price)
+            .insert(&conn)
+            .await
+            .unwrap();
+    }
+
+    let mut rows: Vec<ProductsByCategory> = DbEntity::<Product>::aggregate()
+        .group_by("category", "category", Cast::Text)
+        .count("products")
+        .sum("total", "price", Cast::Numeric)
+        .min("cheapest", "price", Cast::Numeric)
+        .filter(("data->>'category' <> $1", &[&"food"]))
+        .having(("count(*) >= $1", &[&1i64]))
+        .fetch(&conn)
+        .await
+        .unwrap();
+    rows.sort_by(|a, b| a.category.cmp(&b.category));
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].category, "garden");
+    assert_eq!(rows[0].products, 1);
+    assert_eq!(rows[1].category, "tools");
+    assert_eq!(rows[1].products, 2);
+    assert!((rows[1].total - 40.0).abs() < f64::EPSILON);
+    assert!((rows[1].cheapest - 10.0).abs() < f64::EPSILON);
+    assert!((rows[0].total - 5.5).abs() < f64::EPSILON);
+
+    // having parameters are numbered after the filter parameters
+    let rows: Vec<ProductsByCategory> = DbEntity::<Product>::aggregate()
+        .group_by("category", "category", Cast::Text)
+        .count("products")
+        .sum("total", "price", Cast::Numeric)
+        .min("cheapest", "price", Cast::Numeric)
+        .filter(("data->>'category' <> $1", &[&"food"]))
+        .having(("count(*) >= $1", &[&2i64]))
+        .fetch(&conn)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].category, "tools");
+
+    let rows: Vec<ProductsByCategory> = DbEntity::<Product>::aggregate()
+        .group_by("category", "category", Cast::Text)
+        .count("products")
+        .sum("total", "price", Cast::Numeric)
+        .min("cheapest", "price", Cast::Numeric)
+        .filter(("data->>'category' = $1", &[&"missing"]))
+        .fetch(&conn)
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+}
+
+#[orma::test]
+async fn test_find_containing(connection: Connection) {
+    let conn = connection.await;
+
+    for (code, category, price) in &[
+        ("C01", "tools", 10.0),
+        ("C02", "tools", 30.0),
+        ("C03", "food", 2.0),
+    ] {
+        create_product(code, "EU", code, category, *price)
+            .insert(&conn)
+            .await
+            .unwrap();
+    }
+
+    let products: Vec<DbEntity<Product>> = DbEntity::find_containing(
+        &conn,
+        &json!({ "category": "tools" }),
+        Some(("(data->>'price')::numeric > $1::integer", &[&20i32])),
+        Some(&[Sort::asc("name")]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0].data.name, "C02");
+
+    // containment compares json types: a string does not contain a number
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_containing(&conn, &json!({ "price": "10" }), None, None, 0, -1)
+            .await
+            .unwrap();
+    assert!(products.is_empty());
+
+    let filter = DbEntity::<Product>::filter()
+        .has_all_keys(&["name", "price"])
+        .has_any_key(&["category", "missing"])
+        .and("data->>'name' <> $1", &[&"C01"]);
+    let params = filter.params();
+    let products: Vec<DbEntity<Product>> = DbEntity::find_all(
+        &conn,
+        Some((&filter.clause(), &params)),
+        Some(&[Sort::asc("name")]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    assert_eq!(products.len(), 2);
+    assert_eq!(products[0].data.name, "C02");
+
+    let filter = DbEntity::<Product>::filter().has_key("missing");
+    let params = filter.params();
+    let product = DbEntity::<Product>::find_by(&conn, (&filter.clause(), &params))
+        .await
+        .unwrap();
+    assert!(product.is_none());
+}
+
+#[orma::test]
+async fn test_json_path(connection: Connection) {
+    let mut conn = connection.await;
+
+    for (code, tags) in &[("J01", vec!["red", "big"]), ("J02", vec!["blue"])] {
+        let mut product = create_product(code, "EU", code, "tools", 1.0);
+        product.data.tags = tags.iter().map(|&tag| tag.to_owned()).collect();
+        product.insert(&conn).await.unwrap();
+    }
+
+    let filter = DbEntity::<Product>::filter()
+        .path_exists_with("$.tags[*] ? (@ == $tag)", &json!({ "tag": "big" }))
+        .unwrap();
+    let params = filter.params();
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_all(&conn, Some((&filter.clause(), &params)), None, 0, -1)
+            .await
+            .unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0].data.name, "J01");
+
+    let filter = DbEntity::<Product>::filter().path_exists("$.tags[*] ? (@ == \"blue\")");
+    let params = filter.params();
+    let product: DbEntity<Product> = DbEntity::find_by(&conn, (&filter.clause(), &params))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(product.data.name, "J02");
+
+    let filter = DbEntity::<Product>::filter().path_match("$.tags.size() > 2");
+    let params = filter.params();
+    let missing = DbEntity::<Product>::find_by(&conn, (&filter.clause(), &params))
+        .await
+        .unwrap();
+    assert!(missing.is_none());
+
+    // paths are written as literals, so quotes in them are escaped
+    create_product("J03", "EU", "it's", "tools", 1.0)
+        .insert(&conn)
+        .await
+        .unwrap();
+    let filter = DbEntity::<Product>::filter().path_exists("$.name ? (@ == \"it's\")");
+    let params = filter.params();
+    let product: DbEntity<Product> = DbEntity::find_by(&conn, (&filter.clause(), &params))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(product.id, ("J03".to_owned(), "EU".to_owned()));
+
+    let product = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"J02"]))
+        .await
+        .unwrap()
+        .unwrap();
+    let tickets = product_tickets(&product).unwrap();
+    for title in &["first", "second"] {
+        let mut ticket_entity = DbEntity::from_data(new_data!(Ticket, {
+            title: title.to_string(),
+        }));
+        ticket_entity.insert(&conn).await.unwrap();
+        tickets
+            .add_items(&mut conn, &[&ticket_entity])
+            .await
+            .unwrap();
+    }
+    let filter = Filter::new("a.data")
+        .path_match_with("$.title == $title", &json!({ "title": "second" }))
+        .unwrap();
+    let params = filter.params();
+    let found: Vec<DbEntity<Ticket>> = tickets
+        .fetch_filtered(&conn, (&filter.clause(), &params))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].data.title, "second");
+}
+
+#[orma::test]
+async fn test_search(connection: Connection) {
+    let conn = connection.await;
+
+    conn.batch_execute(&search_index_ddl::<Note>().unwrap())
+        .await
+        .unwrap();
+    for text in &[
+        "The quick brown fox jumps over the lazy dog",
+        "Foxes are running in the forest",
+        "Nothing to see here",
+    ] {
+        DbEntity::from_data(new_data!(Note, {
+            text: text.to_string(),
+            stamp: None,
+            edited_by: None,
+        }))
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    let results = DbEntity::<Note>::search(&conn, "fox", &SearchOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].rank >= results[1].rank);
+    assert!(results[0].snippet.is_none());
+
+    let results = DbEntity::<Note>::search(
+        &conn,
+        "running -quick",
+        &SearchOptions {
+            snippets: true,
+            headline_options: Some("StartSel=[, StopSel=]"),
+            limit: 10,
+            ..SearchOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0].entity.data.text,
+        "Foxes are running in the forest"
+    );
+    assert_eq!(
+        results[0].snippet.as_deref(),
+        Some("Foxes are [running] in the forest")
     );
+
+    let results = DbEntity::<Note>::search(
+        &conn,
+        "fox",
+        &SearchOptions {
+            filter: Some(("doc->>'text' LIKE $1", &[&"The%"])),
+            ..SearchOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[orma::test]
+async fn test_similar(connection: Connection) {
+    let conn = connection.await;
+
+    conn.batch_execute(&trigram_index_ddl::<User>("user_name").unwrap())
+        .await
+        .unwrap();
+    for user_name in &["jonathan", "johnny", "maria"] {
+        DbEntity::from_data(create_user(user_name, &format!("{}@test.com", user_name)))
+            .insert(&conn)
+            .await
+            .unwrap();
+    }
+
+    let results =
+        DbEntity::<User>::similar(&conn, "user_name", "jonatan", &SimilarityOptions::default())
+            .await
+            .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].entity.data.user_name, "jonathan");
+    assert!(results[0].score > 0.3);
+
+    let results = DbEntity::<User>::similar(
+        &conn,
+        "user_name",
+        "john",
+        &SimilarityOptions {
+            mode: SimilarityMode::WordSimilarity,
+            threshold: Some(0.1),
+            ..SimilarityOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(results[0].entity.data.user_name, "johnny");
+    assert!(results
+        .windows(2)
+        .all(|pair| pair[0].score >= pair[1].score));
+    assert!(results
+        .iter()
+        .all(|result| result.entity.data.user_name != "maria"));
+}
+
+#[orma::test]
+async fn test_typed_sorting(connection: Connection) {
+    let conn = connection.await;
+
+    for (code, price) in &[("S01", 10.0), ("S02", 2.0), ("S03", 30.0)] {
+        create_product(code, "EU", code, "tools", *price)
+            .insert(&conn)
+            .await
+            .unwrap();
+    }
+
+    let products: Vec<DbEntity<Product>> = DbEntity::find_all(
+        &conn,
+        None,
+        Some(&[Sort::desc("price").cast(Cast::Numeric).nulls_last()]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    let names: Vec<&str> = products.iter().map(|p| p.data.name.as_str()).collect();
+    assert_eq!(names, vec!["S03", "S01", "S02"]);
+
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_all(&conn, None, Some(&[Sort::desc("price")]), 0, -1)
+            .await
+            .unwrap();
+    let names: Vec<&str> = products.iter().map(|p| p.data.name.as_str()).collect();
+    assert_eq!(names, vec!["S03", "S02", "S01"], "text sorting");
+
+    // products without a rank come first or last, whatever the direction
+    conn.execute(
+        "UPDATE intrared.products SET data = data || '{\"rank\": 1}' WHERE code = 'S02'",
+        &[],
+    )
+    .await
+    .unwrap();
+    let products: Vec<DbEntity<Product>> = DbEntity::find_all(
+        &conn,
+        None,
+        Some(&[
+            Sort::asc("rank").cast(Cast::Numeric).nulls_first(),
+            Sort::asc("name"),
+        ]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    let names: Vec<&str> = products.iter().map(|p| p.data.name.as_str()).collect();
+    assert_eq!(names, vec!["S01", "S03", "S02"]);
+    let products: Vec<DbEntity<Product>> = DbEntity::find_all(
+        &conn,
+        None,
+        Some(&[
+            Sort::desc("rank").cast(Cast::Numeric).nulls_last(),
+            Sort::asc("name"),
+        ]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    let names: Vec<&str> = products.iter().map(|p| p.data.name.as_str()).collect();
+    assert_eq!(names, vec!["S02", "S01", "S03"]);
+}
+
+#[orma::test]
+async fn test_identifier_checks(connection: Connection) {
+    let conn = connection.await;
+
+    let mut user_entity = DbEntity::from_data(create_user("test_identifiers", "test@test.com"));
+    user_entity.insert(&conn).await.unwrap();
+
+    let join = JoinBuilder::new(&user_entity.data)
+        .with_join_table(
+            "intrared.r_user_group",
+            "id_user",
+            "id_group; DROP TABLE users",
+        )
+        .with_target("intrared.groups")
+        .build();
+    assert!(join.is_err());
+    let join = JoinBuilder::new(&user_entity.data)
+        .with_join_table("intrared.r_user_group", "id_user", "id_group")
+        .with_target("intrared.groups g")
+        .build();
+    assert!(join.is_err());
+
+    let allowed = [Sort::asc("user_name"), Sort::asc("email").nulls_last()];
+    assert!(Sort::parse_allowed("data->>'user_name'", &allowed).is_err());
+    let sorting = Sort::parse_allowed("-email, user_name", &allowed).unwrap();
+    let users: Vec<DbEntity<User>> = DbEntity::find_all(&conn, None, Some(&sorting), 0, -1)
+        .await
+        .unwrap();
+    assert_eq!(users.len(), 1);
+}
+
+#[derive(Serialize)]
+struct PriceRange<'a> {
+    category: &'a str,
+    min: f64,
+}
+
+#[orma::test]
+async fn test_named_params(connection: Connection) {
+    let conn = connection.await;
+
+    for (code, category, price) in &[
+        ("N01", "tools", 10.0),
+        ("N02", "tools", 30.0),
+        ("N03", "food", 40.0),
+    ] {
+        create_product(code, "EU", code, category, *price)
+            .insert(&conn)
+            .await
+            .unwrap();
+    }
+
+    let (clause, params) = named_params(
+        "data->>'category' = :category AND data->>'name' <> :name",
+        &[("name", &"N01"), ("category", &"tools")],
+    )
+    .unwrap();
+    let product: DbEntity<Product> = DbEntity::find_by(&conn, (&clause, &params))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(product.data.name, "N02");
+
+    let filter = DbEntity::<Product>::filter()
+        .and_named_from(
+            "data->>'category' = :category AND (data->>'price')::float8 >= :min",
+            &PriceRange {
+                category: "tools",
+                min: 20.5,
+            },
+        )
+        .unwrap();
+    let params = filter.params();
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_all(&conn, Some((&filter.clause(), &params)), None, 0, -1)
+            .await
+            .unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0].data.name, "N02");
+
+    // values are cast, so they can be compared with other types (length returns an integer)
+    let filter = DbEntity::<Product>::filter()
+        .and_named_from(
+            "length(data->>'name') = :length AND (data->>'price')::numeric < :max \
+             AND (:category IS NULL OR data->>'category' = :category)",
+            &json!({"length": 3, "max": 35.5, "category": null}),
+        )
+        .unwrap();
+    let params = filter.params();
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_all(&conn, Some((&filter.clause(), &params)), None, 0, -1)
+            .await
+            .unwrap();
+    assert_eq!(products.len(), 2);
+
+    assert!(DbEntity::<Product>::filter()
+        .and_named("data->>'name' = :name", &[("code", &"N01")])
+        .is_err());
+}
+
+#[orma::test]
+async fn test_json_params(connection: Connection) {
+    let conn = connection.await;
+
+    for (code, tags) in &[("P01", vec!["red", "big"]), ("P02", vec!["red"])] {
+        let mut product = create_product(code, "EU", code, "tools", 1.0);
+        product.data.tags = tags.iter().map(|&tag| tag.to_owned()).collect();
+        product.insert(&conn).await.unwrap();
+    }
+
+    let tags = Json(vec!["big"]);
+    let filter = DbEntity::<Product>::filter()
+        .and("data->'tags' @> $1", &[&tags])
+        .and_json("data->'tags' @> $1", &["red"])
+        .unwrap();
+    let params = filter.params();
+    let products: Vec<DbEntity<Product>> =
+        DbEntity::find_all(&conn, Some((&filter.clause(), &params)), None, 0, -1)
+            .await
+            .unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0].data.name, "P01");
+
+    let rows = conn
+        .query(
+            "SELECT data, data->'tags' FROM intrared.products WHERE code = $1",
+            &[&"P02"],
+        )
+        .await
+        .unwrap();
+    let product: Json<Product> = rows[0].get(0);
+    assert_eq!(product.name, "P02");
+    let tags: Json<Vec<String>> = rows[0].get(1);
+    assert_eq!(tags.into_inner(), vec!["red".to_owned()]);
+
+    // missing values are read as None
+    let row = conn
+        .query_one(
+            "SELECT data->'missing' FROM intrared.products WHERE code = $1",
+            &[&"P02"],
+        )
+        .await
+        .unwrap();
+    let missing: Option<Json<Vec<String>>> = row.get(0);
+    assert!(missing.is_none());
+}
+
+#[orma::test]
+async fn test_array_operations(connection: Connection) {
+    let conn = connection.await;
+
+    let mut product = create_product("A01", "EU", "A01", "tools", 1.0);
+    product.data.tags = vec!["red".to_owned()];
+    product.insert(&conn).await.unwrap();
+    let mut stale = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"A01"]))
+        .await
+        .unwrap()
+        .unwrap();
+
+    product.array_append(&conn, "tags", &"big").await.unwrap();
+    assert_eq!(product.version, 2);
+    assert_eq!(product.data.tags, vec!["red", "big"]);
+
+    // no version check: operations on a stale entity are applied to the stored document
+    stale.array_prepend(&conn, "tags", &"new").await.unwrap();
+    assert_eq!(stale.version, 3);
+    assert_eq!(stale.data.tags, vec!["new", "red", "big"]);
+
+    stale.array_append(&conn, "tags", &"red").await.unwrap();
+    stale.array_remove(&conn, "tags", &"red").await.unwrap();
+    assert_eq!(stale.data.tags, vec!["new", "big"]);
+
+    stale.array_remove_at(&conn, "tags", -1).await.unwrap();
+    assert_eq!(stale.data.tags, vec!["new"]);
+    assert_eq!(stale.version, 6);
+
+    stale
+        .array_append(&conn, "meta.labels", &"eco")
+        .await
+        .unwrap();
+    stale
+        .array_prepend(&conn, "extra.meta.labels", &"eco")
+        .await
+        .unwrap();
+    let row = conn
+        .query_one(
+            "SELECT data->'meta', data->'extra' FROM intrared.products WHERE code = 'A01'",
+            &[],
+        )
+        .await
+        .unwrap();
+    let meta: Json<serde_json::Value> = row.get(0);
+    assert_eq!(meta.into_inner(), json!({"labels": ["eco"]}));
+    let extra: Json<serde_json::Value> = row.get(1);
+    assert_eq!(extra.into_inner(), json!({"meta": {"labels": ["eco"]}}));
+    assert_eq!(stale.version, 8);
+
+    // nothing is written when the path does not hold an array, or its parent is not an object
+    assert!(stale.array_append(&conn, "name", &"eco").await.is_err());
+    assert!(stale
+        .array_append(&conn, "name.labels", &"eco")
+        .await
+        .is_err());
+    assert!(stale
+        .array_remove(&conn, "other.labels", &"eco")
+        .await
+        .is_err());
+    assert!(stale.array_remove_at(&conn, "price", 0).await.is_err());
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"A01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 8);
+    assert_eq!(stored.data.name, "A01");
+    assert_eq!(stored.data.price, 1.0);
+    assert_eq!(stale.version, 8);
+
+    stale.delete(&conn).await.unwrap();
+    assert!(product.array_append(&conn, "tags", &"big").await.is_err());
+}
+
+#[orma::test]
+async fn test_increment(connection: Connection) {
+    let conn = connection.await;
+
+    let mut product = create_product("I01", "EU", "I01", "tools", 3.0);
+    product.insert(&conn).await.unwrap();
+
+    let result = product.increment(&conn, "price", 2.0, None).await.unwrap();
+    assert_eq!(result, Some((5.0, 2)));
+    assert_eq!(product.data.price, 5.0);
+
+    let guard = "(data->>'price')::numeric >= $1::float8";
+    let result = product
+        .increment(&conn, "price", -4.0, Some((guard, &[&4.0])))
+        .await
+        .unwrap();
+    assert_eq!(result, Some((1.0, 3)));
+    let result = product
+        .increment(&conn, "price", -4.0, Some((guard, &[&4.0])))
+        .await
+        .unwrap();
+    assert_eq!(result, None);
+    assert_eq!(product.version, 3);
+
+    let result = product.increment(&conn, "stock", 1.0, None).await.unwrap();
+    assert_eq!(result, Some((1.0, 4)));
+
+    // missing parents are created
+    let result = product
+        .increment(&conn, "stats.sales.total", 2.0, None)
+        .await
+        .unwrap();
+    assert_eq!(result, Some((2.0, 5)));
+    let row = conn
+        .query_one(
+            "SELECT data->'stats' FROM intrared.products WHERE code = 'I01'",
+            &[],
+        )
+        .await
+        .unwrap();
+    let stats: Json<serde_json::Value> = row.get(0);
+    assert_eq!(stats.into_inner(), json!({"sales": {"total": 2}}));
+
+    // nothing is written when the path cannot hold a number
+    assert!(product
+        .increment(&conn, "name.length", 1.0, None)
+        .await
+        .is_err());
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"I01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 5);
+    assert_eq!(stored.data.name, "I01");
+    assert_eq!(product.version, 5);
+}
+
+#[orma::test]
+async fn test_merge_patch(connection: Connection) {
+    let conn = connection.await;
+
+    let mut product = create_product("M01", "EU", "M01", "tools", 3.0);
+    product.data.tags = vec!["red".to_owned()];
+    product.insert(&conn).await.unwrap();
+    let mut stale = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"M01"]))
+        .await
+        .unwrap()
+        .unwrap();
+
+    product
+        .merge_patch(
+            &conn,
+            json!({"price": 4.5, "tags": ["blue"], "extra": {"a": 1, "b": null}}),
+        )
+        .await
+        .unwrap();
+    assert_eq!(product.version, 2);
+    assert_eq!(product.data.price, 4.5);
+    assert_eq!(product.data.tags, vec!["blue"]);
+    assert_eq!(product.data.name, "M01");
+
+    product
+        .merge_patch(&conn, json!({"extra": {"b": 2}}))
+        .await
+        .unwrap();
+    let row = conn
+        .query_one(
+            "SELECT data->'extra' FROM intrared.products WHERE code = 'M01'",
+            &[],
+        )
+        .await
+        .unwrap();
+    let extra: Json<serde_json::Value> = row.get(0);
+    assert_eq!(extra.into_inner(), json!({"a": 1, "b": 2}));
+
+    // deleting a required field is rejected and nothing is written
+    assert!(product
+        .merge_patch(&conn, json!({"name": null}))
+        .await
+        .is_err());
+    assert_eq!(product.version, 3);
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"M01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 3);
+    assert_eq!(stored.data.name, "M01");
+
+    assert!(stale.merge_patch(&conn, json!({"price": 1})).await.is_err());
+    assert_eq!(stale.version, 1);
+}
+
+#[orma::test]
+async fn test_json_patch(connection: Connection) {
+    let conn = connection.await;
+
+    let mut product = create_product("J01", "EU", "J01", "tools", 3.0);
+    product.data.tags = vec!["red".to_owned()];
+    product.insert(&conn).await.unwrap();
+
+    product.data.price = 4.0;
+    product.data.tags.push("big".to_owned());
+    let patch = product.diff().unwrap();
+    assert_eq!(
+        serde_json::to_value(&patch).unwrap(),
+        json!([
+            {"op": "replace", "path": "/price", "value": 4.0},
+            {"op": "replace", "path": "/tags", "value": ["red", "big"]},
+        ])
+    );
+    product.update(&conn).await.unwrap();
+    assert!(product.diff().unwrap().is_empty());
+
+    let operations: Vec<PatchOperation> = serde_json::from_value(json!([
+        {"op": "test", "path": "/price", "value": 4},
+        {"op": "add", "path": "/tags/-", "value": "new"},
+        {"op": "add", "path": "/tags/0", "value": "first"},
+        {"op": "remove", "path": "/tags/1"},
+        {"op": "copy", "from": "/name", "path": "/category"},
+        {"op": "move", "from": "/tags/0", "path": "/tags/-"},
+        {"op": "replace", "path": "/price", "value": 5},
+    ]))
+    .unwrap();
+    product.apply_json_patch(&conn, &operations).await.unwrap();
+    assert_eq!(product.version, 3);
+    assert_eq!(product.data.tags, vec!["big", "new", "first"]);
+    assert_eq!(product.data.category, "J01");
+    assert_eq!(product.data.price, 5.0);
+
+    // the failed test prevents the replacement
+    let operations: Vec<PatchOperation> = serde_json::from_value(json!([
+        {"op": "test", "path": "/price", "value": 4},
+        {"op": "replace", "path": "/price", "value": 6},
+    ]))
+    .unwrap();
+    assert!(product.apply_json_patch(&conn, &operations).await.is_err());
+    let operations: Vec<PatchOperation> =
+        serde_json::from_value(json!([{"op": "remove", "path": "/missing"}])).unwrap();
+    assert!(product.apply_json_patch(&conn, &operations).await.is_err());
+    // the patched document is not a valid product
+    let operations: Vec<PatchOperation> =
+        serde_json::from_value(json!([{"op": "remove", "path": "/name"}])).unwrap();
+    assert!(product.apply_json_patch(&conn, &operations).await.is_err());
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"J01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 3);
+    assert_eq!(stored.data.price, 5.0);
+}
+
+#[orma::test]
+async fn test_dirty_tracking(connection: Connection) {
+    let conn = connection.await;
+
+    let mut product = create_product("D01", "EU", "D01", "tools", 3.0);
+    assert!(product.is_dirty().unwrap());
+    product.insert(&conn).await.unwrap();
+    assert!(!product.is_dirty().unwrap());
+    assert!(product.changed_paths().unwrap().is_empty());
+
+    product.update(&conn).await.unwrap();
+    assert_eq!(product.version, 1);
+
+    product.data.price = 4.0;
+    product.data.tags.push("red".to_owned());
+    assert!(product.is_dirty().unwrap());
+    assert_eq!(product.changed_paths().unwrap(), vec!["/price", "/tags"]);
+    product.update(&conn).await.unwrap();
+    assert_eq!(product.version, 2);
+    assert!(!product.is_dirty().unwrap());
+
+    // setting back the loaded value is not a change
+    product.data.price = 5.0;
+    product.data.price = 4.0;
+    product.update(&conn).await.unwrap();
+    assert_eq!(product.version, 2);
+}
+
+#[orma::test]
+async fn test_session(connection: Connection) {
+    let mut conn = connection.await;
+
+    let mut product1 = create_product("S01", "EU", "S01", "tools", 1.0);
+    product1.insert(&conn).await.unwrap();
+    let mut product2 = create_product("S02", "EU", "S02", "tools", 2.0);
+    product2.insert(&conn).await.unwrap();
+
+    let mut session = Session::new(&mut conn);
+    let found = session
+        .find::<Product>(&product1.id)
+        .await
+        .unwrap()
+        .unwrap();
+    found.lock().await.data.price = 10.0;
+    let all = DbEntity::<Product>::find_all(
+        session.connection(),
+        Some(("data->>'category' = $1", &[&"tools"])),
+        Some(&[Sort::asc("name")]),
+        0,
+        -1,
+    )
+    .await
+    .unwrap();
+    let all = session.attach_all(all).unwrap();
+    assert!(Arc::ptr_eq(&found, &all[0]));
+    assert_eq!(all[0].lock().await.data.price, 10.0);
+    let again = session
+        .find::<Product>(&product2.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(Arc::ptr_eq(&again, &all[1]));
+    again.lock().await.data.price = 20.0;
+    session.commit().await.unwrap();
+    assert_eq!(found.lock().await.version, 2);
+
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"S02"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 2);
+    assert_eq!(stored.data.price, 20.0);
+
+    // a version conflict rolls back every change
+    let mut session = Session::new(&mut conn);
+    let first = session.attach(stored).unwrap();
+    first.lock().await.data.price = 30.0;
+    let second = session.attach(product1).unwrap();
+    second.lock().await.data.price = 40.0;
+    assert!(session.commit().await.is_err());
+    let stored = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"S02"]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.data.price, 20.0);
+}
+
+#[orma::test]
+async fn test_unit_of_work(connection: Connection) {
+    let mut conn = connection.await;
+
+    let mut product = create_product("U01", "EU", "U01", "tools", 1.0);
+    product.insert(&conn).await.unwrap();
+    let tickets = product_tickets(&product).unwrap();
+
+    let mut session = Session::new(&mut conn);
+    let product = session.attach(product).unwrap();
+    let ticket = session.register_new(DbEntity::from_data(new_data!(Ticket, {
+        title: "unit of work".to_owned(),
+    })));
+    // join rows are written after the insertions, with the generated ticket id
+    session.add_join_items(&tickets, &[&ticket]);
+    let discarded = session.register_new(create_product("U02", "EU", "U02", "tools", 1.0));
+    session.register_removed(&discarded);
+    session.commit().await.unwrap();
+
+    let ticket_id = ticket.lock().await.id;
+    assert!(ticket_id > 0);
+    let fetched: Vec<DbEntity<Ticket>> = tickets.fetch(&conn).await.unwrap();
+    assert_eq!(fetched.len(), 1);
+    assert_eq!(fetched[0].id, ticket_id);
+    assert!(
+        DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"U02"]))
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // a version conflict rolls back every change
+    let mut stale = DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"U01"]))
+        .await
+        .unwrap()
+        .unwrap();
+    {
+        let mut product = product.lock().await;
+        product.data.price = 2.0;
+        product.update(&conn).await.unwrap();
+    }
+    stale.data.price = 3.0;
+    let mut other = create_product("U04", "EU", "U04", "tools", 1.0);
+    other.insert(&conn).await.unwrap();
+    other.data.price = 4.0;
+    let mut session = Session::new(&mut conn);
+    session.register_new(create_product("U03", "EU", "U03", "tools", 1.0));
+    let new_ticket = session.register_new(DbEntity::from_data(new_data!(Ticket, {
+        title: "rolled back".to_owned(),
+    })));
+    let other = session.attach(other).unwrap();
+    session.attach(stale).unwrap();
+    session.remove_join_items(&tickets, &[&ticket]);
+    assert!(session.commit().await.is_err());
+    assert!(
+        DbEntity::<Product>::find_by(&conn, ("code = $1", &[&"U03"]))
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(tickets.fetch::<Ticket>(&conn).await.unwrap().len(), 1);
+
+    // entities written before the conflict are restored, so their changes are not lost
+    {
+        let new_ticket = new_ticket.lock().await;
+        assert_eq!(new_ticket.id, 0);
+        assert_eq!(new_ticket.data.orma_id, None);
+        assert_eq!(new_ticket.data.orma_version, None);
+    }
+    {
+        let mut other = other.lock().await;
+        assert_eq!(other.version, 1);
+        assert!(other.is_dirty().unwrap());
+        other.update(&conn).await.unwrap();
+        assert_eq!(other.version, 2);
+    }
+
+    let mut session = Session::new(&mut conn);
+    session.remove_join_items(&tickets, &[&ticket]);
+    session.register_removed(&ticket);
+    session.commit().await.unwrap();
+    assert!(tickets.fetch::<Ticket>(&conn).await.unwrap().is_empty());
+    assert!(
+        DbEntity::<Ticket>::find_by(&conn, ("id = $1", &[&ticket_id]))
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[orma::test]
+async fn test_in_transaction(connection: Connection) {
+    let mut conn = connection.await;
+
+    async fn price_of(conn: &Connection, code: &str) -> Option<f64> {
+        DbEntity::<Product>::find_by(conn, ("code = $1", &[&code]))
+            .await
+            .unwrap()
+            .map(|product| product.data.price)
+    }
+
+    let code = conn
+        .in_transaction(|tx| {
+            Box::pin(async move {
+                let mut product = create_product("T01", "EU", "T01", "tools", 1.0);
+                product.insert(tx).await?;
+                Ok(product.data.name)
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(code, "T01");
+    assert_eq!(price_of(&conn, "T01").await, Some(1.0));
+
+    // an error rolls back the transaction
+    let result: Result<(), DbError> = conn
+        .in_transaction(|tx| {
+            Box::pin(async move {
+                create_product("T02", "EU", "T02", "tools", 1.0)
+                    .insert(tx)
+                    .await?;
+                Err(DbError::new("failure", None))
+            })
+        })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(price_of(&conn, "T02").await, None);
+
+    // nested transactions use savepoints
+    conn.in_transaction(|tx| {
+        Box::pin(async move {
+            create_product("T03", "EU", "T03", "tools", 1.0)
+                .insert(tx)
+                .await?;
+            let inner: Result<(), DbError> = tx
+                .in_transaction(|tx| {
+                    Box::pin(async move {
+                        create_product("T04", "EU", "T04", "tools", 1.0)
+                            .insert(tx)
+                            .await?;
+                        Err(DbError::new("inner failure", None))
+                    })
+                })
+                .await;
+            assert!(inner.is_err());
+            Ok(())
+        })
+    })
+    .await
+    .unwrap();
+    assert_eq!(price_of(&conn, "T03").await, Some(1.0));
+    assert_eq!(price_of(&conn, "T04").await, None);
+
+    // a panic rolls back the transaction too
+    let result = AssertUnwindSafe(conn.in_transaction::<_, ()>(|tx| {
+        Box::pin(async move {
+            create_product("T05", "EU", "T05", "tools", 1.0)
+                .insert(tx)
+                .await?;
+            panic!("failure");
+        })
+    }))
+    .catch_unwind()
+    .await;
+    assert!(result.is_err());
+    assert_eq!(price_of(&conn, "T05").await, None);
+
+    // the connection is not left in a transaction
+    assert!(conn.commit().await.is_err());
+}
+
+#[orma::test]
+async fn test_transaction_guard(connection: Connection) {
+    let mut conn = connection.await;
+
+    async fn exists(conn: &Connection, code: &str) -> bool {
+        DbEntity::<Product>::find_by(conn, ("code = $1", &[&code]))
+            .await
+            .unwrap()
+            .is_some()
+    }
+
+    let tx = conn.begin().await.unwrap();
+    create_product("G01", "EU", "G01", "tools", 1.0)
+        .insert(&tx)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    assert!(exists(&conn, "G01").await);
+
+    // dropped without commit
+    {
+        let mut tx = conn.begin().await.unwrap();
+        create_product("G02", "EU", "G02", "tools", 1.0)
+            .insert(&tx)
+            .await
+            .unwrap();
+        let nested = tx.begin().await.unwrap();
+        create_product("G03", "EU", "G03", "tools", 1.0)
+            .insert(&nested)
+            .await
+            .unwrap();
+        nested.commit().await.unwrap();
+        assert!(exists(&tx, "G03").await);
+    }
+    assert!(!exists(&conn, "G02").await);
+    assert!(!exists(&conn, "G03").await);
+
+    // a cancelled future leaves the connection out of any transaction
+    let cancelled = tokio::time::timeout(std::time::Duration::from_millis(100), async {
+        let tx = conn.begin().await.unwrap();
+        create_product("G04", "EU", "G04", "tools", 1.0)
+            .insert(&tx)
+            .await
+            .unwrap();
+        futures::future::pending::<()>().await;
+        tx.commit().await
+    })
+    .await;
+    assert!(cancelled.is_err());
+    assert!(!exists(&conn, "G04").await);
+    assert!(conn.commit().await.is_err());
+}
+
+#[orma::test]
+async fn test_transaction_options(connection: Connection) {
+    let mut conn = connection.await;
+
+    async fn setting(conn: &Connection, name: &str) -> String {
+        conn.query_one("SELECT current_setting($1)", &[&name])
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    let mut tx = conn
+        .build_transaction()
+        .isolation_level(IsolationLevel::Serializable)
+        .read_only(true)
+        .deferrable(true)
+        .begin()
+        .await
+        .unwrap();
+    assert_eq!(setting(&tx, "transaction_isolation").await, "serializable");
+    assert_eq!(setting(&tx, "transaction_read_only").await, "on");
+    assert_eq!(setting(&tx, "transaction_deferrable").await, "on");
+
+    // savepoints inherit the options of the transaction, which cannot be changed
+    assert!(tx
+        .build_transaction()
+        .isolation_level(IsolationLevel::ReadCommitted)
+        .begin()
+        .await
+        .is_err());
+    let nested = tx.begin().await.unwrap();
+    assert_eq!(
+        setting(&nested, "transaction_isolation").await,
+        "serializable"
+    );
+    assert!(create_product("O01", "EU", "O01", "tools", 1.0)
+        .insert(&nested)
+        .await
+        .is_err());
+    drop(nested);
+    tx.rollback().await.unwrap();
+
+    let isolation = conn
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .in_transaction(|tx| {
+            Box::pin(async move {
+                create_product("O02", "EU", "O02", "tools", 1.0)
+                    .insert(tx)
+                    .await?;
+                Ok(setting(tx, "transaction_isolation").await)
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(isolation, "repeatable read");
+    assert_eq!(setting(&conn, "transaction_read_only").await, "off");
 }
@@ -3,6 +3,14 @@ use orma::*;
 
 use serde::{Deserialize, Serialize};
 
+#[derive(Deserialize)]
+pub struct UserName {
+    pub id: Uuid,
+    pub version: i32,
+    pub user_name: String,
+    pub email: String,
+}
+
 #[orma_obj(table = "intrared.users")]
 #[derive(Serialize, Deserialize)]
 pub struct User {
@@ -25,7 +33,7 @@ impl User {
         DbEntity::<User>::find_all(
             db_conn,
             None,
-            Some(&["data->>'user_name' ASC", "data->>'email' DESC"]),
+            Some(&[Sort::asc("user_name"), Sort::desc("email")]),
             0,
             1000,
         )
@@ -37,7 +45,7 @@ pub fn user_groups(user: &DbEntity<User>) -> Result<DbJoin, DbError> {
     let db_join = JoinBuilder::new(&user.data)
         .with_join_table("intrared.r_user_group", "id_user", "id_group")
         .with_target(Group::table_name())
-        .with_sorting(&["data->>'name'"])
+        .with_sorting(&[Sort::asc("name")])
         .build()?;
     Ok(db_join)
 }